@@ -0,0 +1,94 @@
+use brainfuck::parser::Parser;
+use brainfuck::virtual_machine::{Cell, VM};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::fs;
+use std::io;
+
+const TAPE_LENGTH: usize = 1 << 16;
+const STRIDES: [usize; 6] = [1, 2, 3, 4, 8, 16];
+
+// the loops the virtual machine used to run for `[>]`, `[<]` and friends
+fn stride_loop_right(cells: &[u8], start: usize, stride: usize) -> Option<usize> {
+    let mut index = start;
+
+    while index < cells.len() {
+        if cells[index] == 0 {
+            return Some(index);
+        }
+        index += stride;
+    }
+
+    None
+}
+
+fn stride_loop_left(cells: &[u8], start: usize, stride: usize) -> Option<usize> {
+    let mut index = start;
+
+    loop {
+        if cells[index] == 0 {
+            return Some(index);
+        }
+        index = index.checked_sub(stride)?;
+    }
+}
+
+fn scan(c: &mut Criterion) {
+    let mut cells = vec![1u8; TAPE_LENGTH];
+    let last = cells.len() - 1;
+    cells[last] = 0;
+
+    let mut group = c.benchmark_group("find_zero_right");
+
+    for stride in STRIDES.iter() {
+        let start = last % stride;
+
+        group.bench_with_input(BenchmarkId::new("stride loop", stride), stride, |b, &s| {
+            b.iter(|| stride_loop_right(black_box(&cells), start, s))
+        });
+        group.bench_with_input(BenchmarkId::new("u8", stride), stride, |b, &s| {
+            b.iter(|| u8::find_zero_right(black_box(&cells), start, s))
+        });
+    }
+
+    group.finish();
+
+    let mut group = c.benchmark_group("find_zero_left");
+
+    cells[last] = 1;
+    cells[0] = 0;
+
+    for stride in STRIDES.iter() {
+        let start = last / stride * stride;
+
+        group.bench_with_input(BenchmarkId::new("stride loop", stride), stride, |b, &s| {
+            b.iter(|| stride_loop_left(black_box(&cells), start, s))
+        });
+        group.bench_with_input(BenchmarkId::new("u8", stride), stride, |b, &s| {
+            b.iter(|| u8::find_zero_left(black_box(&cells), start, s))
+        });
+    }
+
+    group.finish();
+}
+
+fn hanoi(c: &mut Criterion) {
+    let text = fs::read_to_string("programs/hanoi.b").unwrap();
+    let mut parser = Parser::default();
+
+    parser.parse(&text).unwrap();
+
+    let mut group = c.benchmark_group("programs");
+
+    group.sample_size(10);
+    group.bench_function("hanoi", |b| {
+        b.iter(|| {
+            VM::new(parser.ir())
+                .run(&mut io::sink(), &mut io::empty())
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, scan, hanoi);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+use brainfuck::backend::{emit_rust_function, Target};
+use brainfuck::parser::Parser;
+use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
+
+// expands to a `fn(&mut dyn Read, &mut dyn Write) -> Result<(), RuntimeError>`
+// that runs the program with 8 bit cells, the program is either a string
+// literal or brainfuck written out as tokens
+#[proc_macro]
+pub fn brainfuck(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = TokenStream::from(input);
+    let mut source = Source::default();
+
+    match syn::parse2::<syn::LitStr>(input.clone()) {
+        Ok(literal) => source.push_str(&literal.value(), literal.span()),
+        Err(_) => source.push_tokens(input),
+    }
+
+    let mut parser = Parser::default();
+
+    if let Err(error) = parser.parse(&source.string) {
+        return syn::Error::new(source.span(error.beginning()), error)
+            .to_compile_error()
+            .into();
+    }
+
+    let function = emit_rust_function::<u8>(parser.ir(), &Target::default());
+    let block = format!(
+        "{{\nuse ::brainfuck::virtual_machine::RuntimeError;\n\n{}\nrun\n}}",
+        function
+    );
+
+    block.parse().unwrap()
+}
+
+// the program as a string, with the span of the tokens each byte came from
+#[derive(Default)]
+struct Source {
+    string: String,
+    spans: Vec<(usize, Span)>,
+}
+
+impl Source {
+    fn push_str(&mut self, string: &str, span: Span) {
+        self.spans.push((self.string.len(), span));
+        self.string.push_str(string);
+    }
+
+    fn push_tokens(&mut self, tokens: TokenStream) {
+        for token in tokens {
+            match token {
+                TokenTree::Punct(punct) => {
+                    self.push_str(&punct.as_char().to_string(), punct.span())
+                }
+                TokenTree::Group(group) => match group.delimiter() {
+                    Delimiter::Bracket => {
+                        self.push_str("[", group.span_open());
+                        self.push_tokens(group.stream());
+                        self.push_str("]", group.span_close());
+                    }
+                    _ => self.push_tokens(group.stream()),
+                },
+                // anything else is a comment, as in a source file
+                TokenTree::Ident(_) | TokenTree::Literal(_) => {}
+            }
+        }
+    }
+
+    fn span(&self, index: usize) -> Span {
+        self.spans
+            .iter()
+            .rev()
+            .find(|(beginning, _)| *beginning <= index)
+            .map_or_else(Span::call_site, |(_, span)| *span)
+    }
+}
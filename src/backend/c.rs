@@ -0,0 +1,286 @@
+use std::fmt::Write;
+
+use super::{wrap, Target};
+use crate::ir::{Instruction, IR};
+use crate::virtual_machine::{Cell, EofBehavior};
+
+const PRELUDE: &str = r#"
+static cell *tape;
+static size_t length;
+
+static void fail(const char *message, size_t pc, size_t head, int code)
+{
+    fflush(stdout);
+    fprintf(stderr, "runtime error: %s (pc %zu, head %zu)\n", message, pc, head);
+    exit(code);
+}
+
+static void grow(size_t index)
+{
+    size_t new_length = length > SIZE_MAX / 2 ? SIZE_MAX : length * 2;
+    cell *cells;
+
+    if (new_length <= index)
+        new_length = index + 1;
+    if (new_length > LIMIT)
+        new_length = LIMIT;
+
+    cells = realloc(tape, new_length * sizeof(cell));
+    if (!cells) {
+        fprintf(stderr, "fatal error: out of memory\n");
+        exit(71);
+    }
+
+    memset(cells + length, 0, (new_length - length) * sizeof(cell));
+    tape = cells;
+    length = new_length;
+}
+
+static inline size_t left(size_t head, size_t a, size_t pc)
+{
+    if (head < a)
+        fail("pointer moved left of the first cell", pc, head, 70);
+    return head - a;
+}
+
+static inline size_t right(size_t head, size_t a, size_t pc)
+{
+    size_t index = head + a;
+
+    if (index < head || index >= LIMIT)
+        fail("pointer moved past the end of the tape", pc, head, 70);
+    if (index >= length)
+        grow(index);
+    return index;
+}
+
+static inline cell *at(size_t head, ptrdiff_t offset, size_t pc)
+{
+    size_t index = offset < 0 ? left(head, -(size_t)offset, pc) : right(head, (size_t)offset, pc);
+
+    return &tape[index];
+}
+
+static inline void output(cell value, size_t times, size_t pc, size_t head)
+{
+    while (times--)
+        if (putchar((unsigned char)value) == EOF)
+            fail("i/o error", pc, head, 74);
+}
+"#;
+
+pub fn emit_c<C: Cell>(ir: &IR, target: &Target) -> String {
+    let mut c = String::new();
+    let tape = target.tape_size();
+
+    writeln!(c, "#include <stddef.h>").unwrap();
+    writeln!(c, "#include <stdint.h>").unwrap();
+    writeln!(c, "#include <stdio.h>").unwrap();
+    writeln!(c, "#include <stdlib.h>").unwrap();
+    writeln!(c, "#include <string.h>").unwrap();
+    writeln!(c).unwrap();
+    writeln!(c, "typedef uint{}_t cell;", C::BITS).unwrap();
+    writeln!(c).unwrap();
+    writeln!(
+        c,
+        "#define INITIAL_LENGTH ((size_t){}u)",
+        tape.initial_length()
+    )
+    .unwrap();
+
+    match tape.limit() {
+        usize::MAX => writeln!(c, "#define LIMIT SIZE_MAX").unwrap(),
+        limit => writeln!(c, "#define LIMIT ((size_t){}u)", limit).unwrap(),
+    }
+
+    c.push_str(PRELUDE);
+    writeln!(c).unwrap();
+    writeln!(
+        c,
+        "static inline void input(cell *target, size_t times, size_t pc, size_t head)"
+    )
+    .unwrap();
+    writeln!(c, "{{").unwrap();
+    writeln!(c, "    while (times--) {{").unwrap();
+    writeln!(c, "        int byte;").unwrap();
+    writeln!(c).unwrap();
+    writeln!(c, "        if (fflush(stdout) == EOF)").unwrap();
+    writeln!(c, "            fail(\"i/o error\", pc, head, 74);").unwrap();
+    writeln!(c, "        byte = getchar();").unwrap();
+    writeln!(c, "        if (byte != EOF)").unwrap();
+    writeln!(c, "            *target = (cell)byte;").unwrap();
+    writeln!(c, "        else if (ferror(stdin))").unwrap();
+    writeln!(c, "            fail(\"i/o error\", pc, head, 74);").unwrap();
+
+    match target.eof_behavior() {
+        EofBehavior::Error => {
+            writeln!(c, "        else").unwrap();
+            writeln!(
+                c,
+                "            fail(\"unexpected end of input\", pc, head, 70);"
+            )
+            .unwrap();
+        }
+        EofBehavior::Unchanged => (),
+        EofBehavior::Zero => {
+            writeln!(c, "        else").unwrap();
+            writeln!(c, "            *target = 0;").unwrap();
+        }
+        EofBehavior::MinusOne => {
+            writeln!(c, "        else").unwrap();
+            writeln!(c, "            *target = (cell)-1;").unwrap();
+        }
+    }
+
+    writeln!(c, "    }}").unwrap();
+    writeln!(c, "}}").unwrap();
+    writeln!(c).unwrap();
+    writeln!(c, "int main(void)").unwrap();
+    writeln!(c, "{{").unwrap();
+    writeln!(c, "    size_t head = 0;").unwrap();
+    writeln!(c).unwrap();
+    writeln!(c, "    length = INITIAL_LENGTH;").unwrap();
+    writeln!(c, "    tape = calloc(length, sizeof(cell));").unwrap();
+    writeln!(c, "    if (!tape) {{").unwrap();
+    writeln!(
+        c,
+        "        fprintf(stderr, \"fatal error: out of memory\\n\");"
+    )
+    .unwrap();
+    writeln!(c, "        return 71;").unwrap();
+    writeln!(c, "    }}").unwrap();
+    writeln!(c).unwrap();
+
+    let mut depth = 1;
+
+    for (pc, instruction) in ir.vec().iter().enumerate() {
+        if let Instruction::Close(_) = instruction {
+            depth -= 1;
+        }
+
+        let indent = "    ".repeat(depth);
+
+        for line in statement::<C>(pc, instruction).lines() {
+            if line.is_empty() {
+                writeln!(c).unwrap();
+            } else {
+                writeln!(c, "{}{}", indent, line).unwrap();
+            }
+        }
+
+        if let Instruction::Open(_) = instruction {
+            depth += 1;
+        }
+    }
+
+    writeln!(c).unwrap();
+    writeln!(c, "    free(tape);").unwrap();
+    writeln!(c, "    return fflush(stdout) == EOF ? 74 : 0;").unwrap();
+    writeln!(c, "}}").unwrap();
+
+    c
+}
+
+fn statement<C: Cell>(pc: usize, instruction: &Instruction) -> String {
+    let constant = |value: u64| format!("{}u", wrap::<C>(value));
+    // errors report the cell that was read or written, like the vm
+    let index = |offset: isize| match offset {
+        _ if offset < 0 => format!("head - {}", offset.unsigned_abs()),
+        _ => format!("head + {}", offset),
+    };
+
+    match instruction {
+        Instruction::NOP => String::new(),
+
+        Instruction::Left(a) => format!("head = left(head, {}, {});", a, pc),
+
+        Instruction::Right(a) => format!("head = right(head, {}, {});", a, pc),
+
+        Instruction::Add(a) => format!("tape[head] += {};", constant(*a as u64)),
+
+        Instruction::Sub(a) => format!("tape[head] -= {};", constant(*a as u64)),
+
+        Instruction::Input(times) => format!("input(&tape[head], {}, {}, head);", times, pc),
+
+        Instruction::Output(times) => format!("output(tape[head], {}, {}, head);", times, pc),
+
+        Instruction::Open(_) => String::from("while (tape[head]) {"),
+
+        Instruction::Close(_) => String::from("}"),
+
+        Instruction::Zero => String::from("tape[head] = 0;"),
+
+        Instruction::FindZeroLeft(a) => {
+            format!("while (tape[head])\n    head = left(head, {}, {});", a, pc)
+        }
+
+        Instruction::FindZeroRight(a) => {
+            format!("while (tape[head])\n    head = right(head, {}, {});", a, pc)
+        }
+
+        Instruction::ZeroAddLeft(a) => zero_add(-(*a as isize), pc),
+
+        Instruction::ZeroAddRight(a) => zero_add(*a as isize, pc),
+
+        Instruction::MulAdd(pairs) => {
+            let mut block = String::from("if (tape[head]) {\n    cell value = tape[head];\n\n");
+
+            for (offset, factor) in pairs {
+                writeln!(
+                    block,
+                    "    *at(head, {}, {}) += value * {};",
+                    offset,
+                    pc,
+                    constant(*factor as u64)
+                )
+                .unwrap();
+            }
+
+            block.push_str("    tape[head] = 0;\n}");
+            block
+        }
+
+        Instruction::AddAt(offset, a) => {
+            format!("*at(head, {}, {}) += {};", offset, pc, constant(*a as u64))
+        }
+
+        Instruction::SubAt(offset, a) => {
+            format!("*at(head, {}, {}) -= {};", offset, pc, constant(*a as u64))
+        }
+
+        Instruction::InputAt(offset, times) => format!(
+            "input(at(head, {}, {}), {}, {}, {});",
+            offset,
+            pc,
+            times,
+            pc,
+            index(*offset)
+        ),
+
+        Instruction::OutputAt(offset, times) => format!(
+            "output(*at(head, {}, {}), {}, {}, {});",
+            offset,
+            pc,
+            times,
+            pc,
+            index(*offset)
+        ),
+
+        Instruction::ZeroAt(offset) => format!("*at(head, {}, {}) = 0;", offset, pc),
+
+        Instruction::Set(n) => format!("tape[head] = {};", constant(*n as u64)),
+
+        Instruction::SetAt(offset, n) => {
+            format!("*at(head, {}, {}) = {};", offset, pc, constant(*n as u64))
+        }
+    }
+}
+
+// the target is found before the cell is read because growing the tape moves it
+fn zero_add(offset: isize, pc: usize) -> String {
+    format!(
+        "if (tape[head]) {{\n    cell *target = at(head, {}, {});\n\n    \
+         *target += tape[head];\n    tape[head] = 0;\n}}",
+        offset, pc
+    )
+}
@@ -0,0 +1,511 @@
+use super::x86_64::{
+    self, Assembler, Condition, Label, Memory, ADD, CMP, R10, R12, R8, R9, RAX, RBX, RCX, RDI, RDX,
+    RSI, SUB,
+};
+use super::{wrap, Target};
+use crate::ir::IR;
+use crate::virtual_machine::{Cell, EofBehavior};
+
+// the first segment holds the headers and the data, the code starts on the
+// page after it
+const BASE: u64 = 0x400000;
+const PAGE: u64 = 0x1000;
+const PROGRAM_HEADERS: u16 = 3;
+const DATA: u64 = BASE + 0x100;
+
+// the data starts with the runtime the compiled code expects, laid out as in
+// backend::x86_64, followed by the state of the functions below
+const EOF: i32 = 56;
+const COUNT: i32 = 64;
+const NUMBER: i32 = 72;
+const NUMBER_END: i32 = NUMBER + 24;
+const BYTE: i32 = 96;
+const BUFFER: i32 = 104;
+const BUFFER_SIZE: usize = 4096;
+
+const READ: u64 = 0;
+const WRITE: u64 = 1;
+const MMAP: u64 = 9;
+const MREMAP: u64 = 25;
+const EXIT_GROUP: u64 = 231;
+
+const PROT_READ_WRITE: u64 = 0x3;
+const MAP_PRIVATE_ANONYMOUS: u64 = 0x22;
+const MREMAP_MAYMOVE: u64 = 0x1;
+
+// system calls return errors as the last 4095 values of rax
+const MAX_ERRNO: usize = 4095;
+
+// where the text of a message is in the data and how long it is
+#[derive(Clone, Copy)]
+struct Message(i32, u64);
+
+struct Messages {
+    underflow: Message,
+    overflow: Message,
+    eof: Message,
+    io: Message,
+    head: Message,
+    close: Message,
+    out_of_memory: Message,
+}
+
+struct Runtime<C: Cell> {
+    asm: Assembler,
+    target: Target,
+    messages: Messages,
+    flush: Label,
+    write_error: Label,
+    write_number: Label,
+    out_of_memory: Label,
+    exit: Label,
+    accept: Label,
+    refuse: Label,
+    cell: std::marker::PhantomData<C>,
+}
+
+fn data(offset: i32) -> Memory {
+    Memory {
+        base: R8,
+        index: None,
+        displacement: offset,
+    }
+}
+
+impl<C: Cell> Runtime<C> {
+    fn shift(&self) -> u8 {
+        (C::BITS / 8).trailing_zeros() as u8
+    }
+
+    fn limit(&self) -> usize {
+        self.target.tape_size().limit()
+    }
+
+    fn shift_left(&mut self, reg: u8) {
+        if self.shift() > 0 {
+            self.asm.registers(&[0xc1], 4, reg);
+            self.asm.bytes(&[self.shift()]);
+        }
+    }
+
+    fn system_call(&mut self, number: u64) {
+        self.asm.mov_immediate(RAX, number);
+        self.asm.syscall();
+    }
+
+    // jumps to the label when rax holds an error from a system call
+    fn check_error(&mut self, label: Label) {
+        self.asm
+            .arithmetic(CMP, RAX, (MAX_ERRNO + 1).wrapping_neg());
+        self.asm.jump(Some(Condition::Above), label);
+    }
+
+    fn message(&mut self, message: Message) {
+        self.asm.lea(RSI, data(message.0));
+        self.asm.mov_immediate(RDX, message.1);
+    }
+
+    // writes the buffered output, r8 holds the data in this and every function
+    // below that is not called from the compiled code
+    fn flush(&mut self) {
+        let (next, flushed) = (self.asm.label(), self.asm.label());
+
+        self.asm.bind(self.flush);
+        self.asm.load(RDX, data(COUNT));
+        self.asm.lea(RSI, data(BUFFER));
+        self.asm.bind(next);
+        self.asm.arithmetic(CMP, RDX, 0);
+        self.asm.jump(Some(Condition::Equal), flushed);
+        self.asm.mov_immediate(RDI, 1);
+        self.system_call(WRITE);
+        self.asm.arithmetic(CMP, RAX, 0);
+        self.asm.jump(Some(Condition::LessOrEqual), self.refuse);
+        self.asm.registers(&[0x01], RAX, RSI);
+        self.asm.registers(&[0x29], RAX, RDX);
+        self.asm.jump(None, next);
+        self.asm.bind(flushed);
+        self.asm.cell_set(64, data(COUNT), 0);
+        self.asm.jump(None, self.accept);
+    }
+
+    // writes rax in decimal, then falls through to write the text at rsi
+    fn write_number(&mut self) {
+        let digit = self.asm.label();
+
+        self.asm.bind(self.write_number);
+        self.asm.lea(RSI, data(NUMBER_END));
+        self.asm.mov_immediate(RCX, 10);
+        self.asm.bind(digit);
+        self.asm.mov_immediate(RDX, 0);
+        self.asm.registers(&[0xf7], 6, RCX);
+        self.asm.arithmetic(ADD, RDX, b'0' as usize);
+        self.asm.arithmetic(SUB, RSI, 1);
+        self.asm.memory(
+            8,
+            &[0x88],
+            RDX,
+            Memory {
+                base: RSI,
+                index: None,
+                displacement: 0,
+            },
+        );
+        self.asm.arithmetic(CMP, RAX, 0);
+        self.asm.jump(Some(Condition::NotEqual), digit);
+        self.asm.lea(RDX, data(NUMBER_END));
+        self.asm.registers(&[0x29], RSI, RDX);
+
+        self.asm.bind(self.write_error);
+        self.asm.mov_immediate(RDI, 2);
+        self.system_call(WRITE);
+        self.asm.ret();
+    }
+
+    fn out_of_memory(&mut self) {
+        self.asm.bind(self.out_of_memory);
+        self.asm.mov_immediate(R8, DATA);
+        self.asm.call_label(self.flush);
+        self.message(self.messages.out_of_memory);
+        self.asm.call_label(self.write_error);
+        self.asm.mov_immediate(RDI, exitcode::OSERR as u64);
+        self.asm.bind(self.exit);
+        self.system_call(EXIT_GROUP);
+    }
+
+    // grows the tape like Tape::reserve, at least doubling it up to the limit
+    fn reserve(&mut self) -> usize {
+        let (larger, capped) = (self.asm.label(), self.asm.label());
+        let position = self.asm.position();
+
+        self.asm.mov(R8, RDI);
+        self.asm.arithmetic(CMP, RSI, self.limit());
+        self.asm.jump(Some(Condition::AboveOrEqual), self.refuse);
+        self.asm.lea(
+            R9,
+            Memory {
+                base: RSI,
+                index: None,
+                displacement: 1,
+            },
+        );
+        self.asm.load(RAX, data(x86_64::LENGTH));
+        self.asm.registers(&[0x01], RAX, RAX);
+        self.asm.registers(&[0x39], RAX, R9);
+        self.asm.jump(Some(Condition::AboveOrEqual), larger);
+        self.asm.mov(R9, RAX);
+        self.asm.bind(larger);
+        self.asm.arithmetic(CMP, R9, self.limit());
+        self.asm.jump(Some(Condition::Below), capped);
+        self.asm.mov_immediate(R9, self.limit() as u64);
+        self.asm.bind(capped);
+
+        self.asm.load(RDI, data(x86_64::CELLS));
+        self.asm.load(RSI, data(x86_64::LENGTH));
+        self.shift_left(RSI);
+        self.asm.mov(RDX, R9);
+        self.shift_left(RDX);
+        self.asm.mov_immediate(R10, MREMAP_MAYMOVE);
+        self.system_call(MREMAP);
+        self.check_error(self.out_of_memory);
+        self.asm.store(data(x86_64::CELLS), RAX);
+        self.asm.store(data(x86_64::LENGTH), R9);
+        self.asm.jump(None, self.accept);
+
+        position
+    }
+
+    fn input(&mut self) -> usize {
+        let (next, eof, step) = (self.asm.label(), self.asm.label(), self.asm.label());
+        let width = C::BITS;
+        let cell = Memory {
+            base: RAX,
+            index: Some((R10, self.shift())),
+            displacement: 0,
+        };
+        let position = self.asm.position();
+
+        self.asm.mov(R8, RDI);
+        self.asm.mov(R10, RSI);
+        self.asm.mov(R9, RDX);
+        self.asm.bind(next);
+        self.asm.arithmetic(CMP, R9, 0);
+        self.asm.jump(Some(Condition::Equal), self.accept);
+        self.asm.call_label(self.flush);
+        self.asm.test_al();
+        self.asm.jump(Some(Condition::Equal), self.refuse);
+
+        self.asm.mov_immediate(RDI, 0);
+        self.asm.lea(RSI, data(BYTE));
+        self.asm.mov_immediate(RDX, 1);
+        self.system_call(READ);
+        self.asm.arithmetic(CMP, RAX, 0);
+        self.asm.jump(Some(Condition::Less), self.refuse);
+        self.asm.jump(Some(Condition::Equal), eof);
+        self.asm.cell_load(8, RCX, data(BYTE));
+        self.asm.load(RAX, data(x86_64::CELLS));
+        self.asm.cell_update(width, 0x88, cell, RCX);
+        self.asm.jump(None, step);
+
+        self.asm.bind(eof);
+
+        match self.target.eof_behavior() {
+            EofBehavior::Error => {
+                self.asm.cell_set(64, data(EOF), 1);
+                self.asm.jump(None, self.refuse);
+            }
+            EofBehavior::Unchanged => (),
+            EofBehavior::Zero => {
+                self.asm.load(RAX, data(x86_64::CELLS));
+                self.asm.cell_set(width, cell, 0);
+            }
+            EofBehavior::MinusOne => {
+                self.asm.load(RAX, data(x86_64::CELLS));
+                self.asm.cell_set(width, cell, wrap::<C>(u64::MAX));
+            }
+        }
+
+        self.asm.bind(step);
+        self.asm.arithmetic(SUB, R9, 1);
+        self.asm.jump(None, next);
+
+        position
+    }
+
+    fn output(&mut self) -> usize {
+        let (next, store) = (self.asm.label(), self.asm.label());
+        let position = self.asm.position();
+
+        self.asm.mov(R8, RDI);
+        self.asm.mov(R10, RSI);
+        self.asm.mov(R9, RDX);
+        self.asm.bind(next);
+        self.asm.arithmetic(CMP, R9, 0);
+        self.asm.jump(Some(Condition::Equal), self.accept);
+        self.asm.load(RAX, data(COUNT));
+        self.asm.arithmetic(CMP, RAX, BUFFER_SIZE);
+        self.asm.jump(Some(Condition::Below), store);
+        self.asm.call_label(self.flush);
+        self.asm.test_al();
+        self.asm.jump(Some(Condition::Equal), self.refuse);
+        self.asm.load(RAX, data(COUNT));
+
+        self.asm.bind(store);
+        self.asm.mov(RCX, R10);
+        self.asm.cell_update(
+            8,
+            0x88,
+            Memory {
+                base: R8,
+                index: Some((RAX, 0)),
+                displacement: BUFFER,
+            },
+            RCX,
+        );
+        self.asm.arithmetic(ADD, RAX, 1);
+        self.asm.store(data(COUNT), RAX);
+        self.asm.arithmetic(SUB, R9, 1);
+        self.asm.jump(None, next);
+
+        position
+    }
+
+    // maps the tape, runs the program and reports how it ended like the C
+    // backend does
+    fn start(&mut self, program: Label) -> usize {
+        let tape = self.target.tape_size().initial_length() << self.shift();
+        let errors = [
+            (x86_64::POINTER_UNDERFLOW, self.messages.underflow),
+            (x86_64::POINTER_OVERFLOW, self.messages.overflow),
+        ];
+        let (failed, io, report, io_exit) = (
+            self.asm.label(),
+            self.asm.label(),
+            self.asm.label(),
+            self.asm.label(),
+        );
+        let position = self.asm.position();
+
+        self.asm.mov_immediate(RDI, 0);
+        self.asm.mov_immediate(RSI, tape as u64);
+        self.asm.mov_immediate(RDX, PROT_READ_WRITE);
+        self.asm.mov_immediate(R10, MAP_PRIVATE_ANONYMOUS);
+        self.asm.mov_immediate(R8, u64::MAX);
+        self.asm.mov_immediate(R9, 0);
+        self.system_call(MMAP);
+        self.check_error(self.out_of_memory);
+        self.asm.mov_immediate(R8, DATA);
+        self.asm.store(data(x86_64::CELLS), RAX);
+
+        self.asm.mov(RDI, R8);
+        self.asm.call_label(program);
+        self.asm.mov(RBX, RAX);
+        self.asm.mov_immediate(R8, DATA);
+        self.asm.call_label(self.flush);
+        self.asm.arithmetic(CMP, RBX, x86_64::FINISHED as usize);
+        self.asm.jump(Some(Condition::NotEqual), failed);
+        self.asm.test_al();
+        self.asm.jump(Some(Condition::Equal), io_exit);
+        self.asm.mov_immediate(RDI, 0);
+        self.asm.jump(None, self.exit);
+
+        self.asm.bind(failed);
+
+        for (code, message) in &errors {
+            let next = self.asm.label();
+
+            self.asm.arithmetic(CMP, RBX, *code as usize);
+            self.asm.jump(Some(Condition::NotEqual), next);
+            self.message(*message);
+            self.asm.mov_immediate(R12, exitcode::SOFTWARE as u64);
+            self.asm.jump(None, report);
+            self.asm.bind(next);
+        }
+
+        // the input function marks an unexpected end of input, anything else
+        // that stopped the program was a failed read or write
+        self.asm.cell_arithmetic(64, CMP, data(EOF), 0);
+        self.asm.jump(Some(Condition::Equal), io);
+        self.message(self.messages.eof);
+        self.asm.mov_immediate(R12, exitcode::SOFTWARE as u64);
+        self.asm.jump(None, report);
+        self.asm.bind(io);
+        self.message(self.messages.io);
+        self.asm.mov_immediate(R12, exitcode::IOERR as u64);
+
+        self.asm.bind(report);
+        self.asm.call_label(self.write_error);
+        self.asm.load(RAX, data(x86_64::PC));
+        self.asm.call_label(self.write_number);
+        self.message(self.messages.head);
+        self.asm.call_label(self.write_error);
+        self.asm.load(RAX, data(x86_64::HEAD));
+        self.asm.call_label(self.write_number);
+        self.message(self.messages.close);
+        self.asm.call_label(self.write_error);
+        self.asm.mov(RDI, R12);
+        self.asm.jump(None, self.exit);
+
+        self.asm.bind(io_exit);
+        self.asm.mov_immediate(RDI, exitcode::IOERR as u64);
+        self.asm.jump(None, self.exit);
+
+        position
+    }
+}
+
+fn program_header(elf: &mut Vec<u8>, kind: u32, flags: u32, segment: (u64, u64, u64), align: u64) {
+    let (offset, address, size) = segment;
+
+    elf.extend_from_slice(&kind.to_le_bytes());
+    elf.extend_from_slice(&flags.to_le_bytes());
+    elf.extend_from_slice(&offset.to_le_bytes());
+    elf.extend_from_slice(&address.to_le_bytes());
+    elf.extend_from_slice(&address.to_le_bytes());
+    elf.extend_from_slice(&size.to_le_bytes());
+    elf.extend_from_slice(&size.to_le_bytes());
+    elf.extend_from_slice(&align.to_le_bytes());
+}
+
+// a static x86-64 linux executable that needs nothing but the kernel
+pub fn emit_elf<C: Cell>(ir: &IR, target: &Target) -> Vec<u8> {
+    let mut data = vec![0; BUFFER as usize + BUFFER_SIZE];
+    let mut text = |text: &str| {
+        let message = Message(data.len() as i32, text.len() as u64);
+
+        data.extend_from_slice(text.as_bytes());
+        message
+    };
+    let messages = Messages {
+        underflow: text("runtime error: pointer moved left of the first cell (pc "),
+        overflow: text("runtime error: pointer moved past the end of the tape (pc "),
+        eof: text("runtime error: unexpected end of input (pc "),
+        io: text("runtime error: i/o error (pc "),
+        head: text(", head "),
+        close: text(")\n"),
+        out_of_memory: text("fatal error: out of memory\n"),
+    };
+
+    let data_end = DATA - BASE + data.len() as u64;
+    let code_offset = data_end.div_ceil(PAGE) * PAGE;
+    let code_address = BASE + code_offset;
+
+    let mut asm = Assembler::new();
+    let program = asm.label();
+
+    asm.bind(program);
+    asm.bytes(&x86_64::compile::<C>(ir));
+
+    let mut runtime = Runtime::<C> {
+        flush: asm.label(),
+        write_error: asm.label(),
+        write_number: asm.label(),
+        out_of_memory: asm.label(),
+        exit: asm.label(),
+        accept: asm.label(),
+        refuse: asm.label(),
+        asm,
+        target: *target,
+        messages,
+        cell: std::marker::PhantomData,
+    };
+
+    let start = runtime.start(program);
+    let reserve = runtime.reserve();
+    let input = runtime.input();
+    let output = runtime.output();
+
+    runtime.flush();
+    runtime.write_number();
+    runtime.out_of_memory();
+
+    runtime.asm.bind(runtime.accept);
+    runtime.asm.mov_immediate(RAX, 1);
+    runtime.asm.ret();
+    runtime.asm.bind(runtime.refuse);
+    runtime.asm.mov_immediate(RAX, 0);
+    runtime.asm.ret();
+
+    let code = runtime.asm.finish();
+    let fields = [
+        (x86_64::LENGTH, target.tape_size().initial_length() as u64),
+        (x86_64::RESERVE, code_address + reserve as u64),
+        (x86_64::INPUT, code_address + input as u64),
+        (x86_64::OUTPUT, code_address + output as u64),
+    ];
+
+    for (offset, value) in &fields {
+        let offset = *offset as usize;
+        data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    let mut elf = b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0".to_vec();
+
+    elf.extend_from_slice(&2u16.to_le_bytes());
+    elf.extend_from_slice(&0x3eu16.to_le_bytes());
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&(code_address + start as u64).to_le_bytes());
+    elf.extend_from_slice(&64u64.to_le_bytes());
+    elf.extend_from_slice(&0u64.to_le_bytes());
+    elf.extend_from_slice(&0u32.to_le_bytes());
+
+    for size in &[64, 56, PROGRAM_HEADERS, 64, 0, 0] {
+        elf.extend_from_slice(&size.to_le_bytes());
+    }
+
+    program_header(&mut elf, 1, 0x6, (0, BASE, data_end), PAGE);
+    program_header(
+        &mut elf,
+        1,
+        0x5,
+        (code_offset, code_address, code.len() as u64),
+        PAGE,
+    );
+    program_header(&mut elf, 0x6474e551, 0x6, (0, 0, 0), 16);
+
+    elf.resize((DATA - BASE) as usize, 0);
+    elf.extend_from_slice(&data);
+    elf.resize(code_offset as usize, 0);
+    elf.extend_from_slice(&code);
+
+    elf
+}
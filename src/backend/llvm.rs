@@ -0,0 +1,622 @@
+use std::fmt::Write;
+
+use super::{wrap, Target};
+use crate::ir::{Instruction, IR};
+use crate::virtual_machine::{Cell, EofBehavior};
+
+const MESSAGES: &[(&str, &str)] = &[
+    ("format", "runtime error: %s (pc %zu, head %zu)\n"),
+    ("underflow", "pointer moved left of the first cell"),
+    ("overflow", "pointer moved past the end of the tape"),
+    ("eof", "unexpected end of input"),
+    ("io", "i/o error"),
+    ("oom", "fatal error: out of memory\n"),
+];
+
+const DECLARATIONS: &str = r#"
+@stdin = external global i8*
+@stderr = external global i8*
+
+declare i32 @getchar()
+declare i32 @putchar(i32)
+declare i32 @fflush(i8*)
+declare i32 @ferror(i8*)
+declare i32 @fputs(i8*, i8*)
+declare i32 @fprintf(i8*, i8*, ...)
+declare i8* @calloc(i64, i64)
+declare i8* @realloc(i8*, i64)
+declare i8* @memset(i8*, i32, i64)
+declare void @free(i8*)
+declare void @exit(i32) noreturn
+"#;
+
+// the runtime is written for the cell type passed in as `cell`, the same
+// way the c backend uses a typedef
+fn runtime(cell: &str, byte: &str, value: &str, size: u32, limit: i64, eof: &str) -> String {
+    format!(
+        r#"
+@tape = internal global {cell}* null
+@length = internal global i64 0
+
+define internal void @fail(i8* %message, i64 %pc, i64 %head, i32 %code) noreturn cold {{
+  %flushed = call i32 @fflush(i8* null)
+  %stderr = load i8*, i8** @stderr
+  %printed = call i32 (i8*, i8*, ...) @fprintf(i8* %stderr, i8* {format}, i8* %message, i64 %pc, i64 %head)
+  call void @exit(i32 %code)
+  unreachable
+}}
+
+define internal void @out_of_memory() noreturn cold {{
+  %stderr = load i8*, i8** @stderr
+  %printed = call i32 @fputs(i8* {oom}, i8* %stderr)
+  call void @exit(i32 71)
+  unreachable
+}}
+
+define internal void @grow(i64 %index) noinline cold {{
+entry:
+  %length = load i64, i64* @length
+  %huge = icmp ugt i64 %length, 9223372036854775807
+  %twice = shl i64 %length, 1
+  %doubled = select i1 %huge, i64 -1, i64 %twice
+  %short = icmp ule i64 %doubled, %index
+  %needed = add i64 %index, 1
+  %wanted = select i1 %short, i64 %needed, i64 %doubled
+  %over = icmp ugt i64 %wanted, {limit}
+  %new_length = select i1 %over, i64 {limit}, i64 %wanted
+  %tape = load {cell}*, {cell}** @tape
+  %old = bitcast {cell}* %tape to i8*
+  %bytes = mul i64 %new_length, {size}
+  %cells = call i8* @realloc(i8* %old, i64 %bytes)
+  %failed = icmp eq i8* %cells, null
+  br i1 %failed, label %oom, label %clear
+
+oom:
+  call void @out_of_memory()
+  unreachable
+
+clear:
+  %used = mul i64 %length, {size}
+  %tail = getelementptr i8, i8* %cells, i64 %used
+  %added = sub i64 %bytes, %used
+  %cleared = call i8* @memset(i8* %tail, i32 0, i64 %added)
+  %grown = bitcast i8* %cells to {cell}*
+  store {cell}* %grown, {cell}** @tape
+  store i64 %new_length, i64* @length
+  ret void
+}}
+
+define internal i64 @left(i64 %head, i64 %a, i64 %pc) alwaysinline {{
+entry:
+  %under = icmp ult i64 %head, %a
+  br i1 %under, label %error, label %move
+
+error:
+  call void @fail(i8* {underflow}, i64 %pc, i64 %head, i32 70)
+  unreachable
+
+move:
+  %index = sub i64 %head, %a
+  ret i64 %index
+}}
+
+define internal i64 @right(i64 %head, i64 %a, i64 %pc) alwaysinline {{
+entry:
+  %index = add i64 %head, %a
+  %wrapped = icmp ult i64 %index, %head
+  %limit = icmp uge i64 %index, {limit}
+  %over = or i1 %wrapped, %limit
+  br i1 %over, label %error, label %check
+
+error:
+  call void @fail(i8* {overflow}, i64 %pc, i64 %head, i32 70)
+  unreachable
+
+check:
+  %length = load i64, i64* @length
+  %outside = icmp uge i64 %index, %length
+  br i1 %outside, label %grow, label %move
+
+grow:
+  call void @grow(i64 %index)
+  br label %move
+
+move:
+  ret i64 %index
+}}
+
+define internal {cell}* @at(i64 %head, i64 %offset, i64 %pc) alwaysinline {{
+entry:
+  %negative = icmp slt i64 %offset, 0
+  br i1 %negative, label %left, label %right
+
+left:
+  %a = sub i64 0, %offset
+  %below = call i64 @left(i64 %head, i64 %a, i64 %pc)
+  br label %cell
+
+right:
+  %above = call i64 @right(i64 %head, i64 %offset, i64 %pc)
+  br label %cell
+
+cell:
+  %index = phi i64 [ %below, %left ], [ %above, %right ]
+  %tape = load {cell}*, {cell}** @tape
+  %pointer = getelementptr {cell}, {cell}* %tape, i64 %index
+  ret {cell}* %pointer
+}}
+
+define internal void @output({cell} %value, i64 %times, i64 %pc, i64 %head) {{
+entry:
+  %byte = {byte}
+  %character = zext i8 %byte to i32
+  br label %loop
+
+loop:
+  %i = phi i64 [ 0, %entry ], [ %next, %write ]
+  %more = icmp ult i64 %i, %times
+  br i1 %more, label %write, label %done
+
+write:
+  %written = call i32 @putchar(i32 %character)
+  %failed = icmp eq i32 %written, -1
+  %next = add i64 %i, 1
+  br i1 %failed, label %error, label %loop
+
+error:
+  call void @fail(i8* {io}, i64 %pc, i64 %head, i32 74)
+  unreachable
+
+done:
+  ret void
+}}
+
+define internal void @input({cell}* %target, i64 %times, i64 %pc, i64 %head) {{
+entry:
+  br label %loop
+
+loop:
+  %i = phi i64 [ 0, %entry ], [ %next, %continue ]
+  %more = icmp ult i64 %i, %times
+  br i1 %more, label %flush, label %done
+
+flush:
+  %flushed = call i32 @fflush(i8* null)
+  %unflushed = icmp ne i32 %flushed, 0
+  br i1 %unflushed, label %error, label %read
+
+read:
+  %character = call i32 @getchar()
+  %end = icmp eq i32 %character, -1
+  br i1 %end, label %eof, label %store
+
+store:
+  %byte = trunc i32 %character to i8
+  %value = {value}
+  store {cell} %value, {cell}* %target
+  br label %continue
+
+eof:
+  %stdin = load i8*, i8** @stdin
+  %failed = call i32 @ferror(i8* %stdin)
+  %broken = icmp ne i32 %failed, 0
+  br i1 %broken, label %error, label %exhausted
+
+exhausted:
+{eof}continue:
+  %next = add i64 %i, 1
+  br label %loop
+
+error:
+  call void @fail(i8* {io}, i64 %pc, i64 %head, i32 74)
+  unreachable
+
+done:
+  ret void
+}}
+"#,
+        cell = cell,
+        byte = byte,
+        value = value,
+        size = size,
+        limit = limit,
+        eof = eof,
+        format = message("format"),
+        oom = message("oom"),
+        underflow = message("underflow"),
+        overflow = message("overflow"),
+        io = message("io"),
+    )
+}
+
+pub fn emit_llvm<C: Cell>(ir: &IR, target: &Target) -> String {
+    let mut ll = String::new();
+    let cell = format!("i{}", C::BITS);
+    let tape = target.tape_size();
+
+    for (name, message) in MESSAGES {
+        writeln!(
+            ll,
+            "@{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"",
+            name,
+            message.len() + 1,
+            escape(message)
+        )
+        .unwrap();
+    }
+
+    ll.push_str(DECLARATIONS);
+
+    let eof = match target.eof_behavior() {
+        EofBehavior::Error => format!(
+            "  call void @fail(i8* {}, i64 %pc, i64 %head, i32 70)\n  unreachable\n",
+            message("eof")
+        ),
+        EofBehavior::Unchanged => String::from("  br label %continue\n"),
+        EofBehavior::Zero => format!("  store {0} 0, {0}* %target\n  br label %continue\n", cell),
+        EofBehavior::MinusOne => {
+            format!("  store {0} -1, {0}* %target\n  br label %continue\n", cell)
+        }
+    };
+    let (byte, value) = match C::BITS {
+        8 => (
+            String::from("add i8 %value, 0"),
+            String::from("add i8 %byte, 0"),
+        ),
+        _ => (
+            format!("trunc {} %value to i8", cell),
+            format!("zext i8 %byte to {}", cell),
+        ),
+    };
+
+    ll.push_str(&runtime(
+        &cell,
+        &byte,
+        &value,
+        C::BITS / 8,
+        tape.limit() as u64 as i64,
+        &eof,
+    ));
+    writeln!(ll).unwrap();
+    writeln!(ll, "define i32 @main() {{").unwrap();
+    writeln!(ll, "entry:").unwrap();
+    writeln!(ll, "  %head = alloca i64").unwrap();
+    writeln!(ll, "  store i64 0, i64* %head").unwrap();
+    writeln!(
+        ll,
+        "  %cells = call i8* @calloc(i64 {}, i64 {})",
+        tape.initial_length(),
+        C::BITS / 8
+    )
+    .unwrap();
+    writeln!(ll, "  %failed = icmp eq i8* %cells, null").unwrap();
+    writeln!(ll, "  br i1 %failed, label %oom, label %start").unwrap();
+    writeln!(ll).unwrap();
+    writeln!(ll, "oom:").unwrap();
+    writeln!(ll, "  call void @out_of_memory()").unwrap();
+    writeln!(ll, "  unreachable").unwrap();
+    writeln!(ll).unwrap();
+    writeln!(ll, "start:").unwrap();
+    writeln!(ll, "  %tape = bitcast i8* %cells to {}*", cell).unwrap();
+    writeln!(ll, "  store {0}* %tape, {0}** @tape", cell).unwrap();
+    writeln!(ll, "  store i64 {}, i64* @length", tape.initial_length()).unwrap();
+
+    for (pc, instruction) in ir.vec().iter().enumerate() {
+        lower::<C>(&mut ll, &cell, pc, instruction);
+    }
+
+    writeln!(ll, "  %flushed = call i32 @fflush(i8* null)").unwrap();
+    writeln!(ll, "  %cleanup = load {0}*, {0}** @tape", cell).unwrap();
+    writeln!(ll, "  %freed = bitcast {}* %cleanup to i8*", cell).unwrap();
+    writeln!(ll, "  call void @free(i8* %freed)").unwrap();
+    writeln!(ll, "  %unflushed = icmp ne i32 %flushed, 0").unwrap();
+    writeln!(ll, "  %code = select i1 %unflushed, i32 74, i32 0").unwrap();
+    writeln!(ll, "  ret i32 %code").unwrap();
+    writeln!(ll, "}}").unwrap();
+
+    ll
+}
+
+fn message(name: &str) -> String {
+    let length = MESSAGES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, message)| message.len() + 1)
+        .unwrap();
+
+    format!(
+        "getelementptr inbounds ([{0} x i8], [{0} x i8]* @{1}, i64 0, i64 0)",
+        length, name
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b' '..=b'~' if byte != b'"' && byte != b'\\' => (byte as char).to_string(),
+            _ => format!("\\{:02X}", byte),
+        })
+        .collect()
+}
+
+// constants are printed signed, the way llvm prints them back
+fn constant<C: Cell>(value: u64) -> i64 {
+    let shift = 64 - C::BITS;
+    ((wrap::<C>(value) << shift) as i64) >> shift
+}
+
+// loads the head and a pointer to its cell into %h.pc and %p.pc, and the cell into %v.pc
+fn current(ll: &mut String, cell: &str, pc: usize) {
+    writeln!(ll, "  %h.{} = load i64, i64* %head", pc).unwrap();
+    writeln!(ll, "  %t.{0} = load {1}*, {1}** @tape", pc, cell).unwrap();
+    writeln!(
+        ll,
+        "  %p.{0} = getelementptr {1}, {1}* %t.{0}, i64 %h.{0}",
+        pc, cell
+    )
+    .unwrap();
+    writeln!(ll, "  %v.{0} = load {1}, {1}* %p.{0}", pc, cell).unwrap();
+}
+
+// a pointer to the cell at the offset from the head in %q.pc
+fn offset(ll: &mut String, cell: &str, pc: usize, offset: isize, suffix: &str) {
+    writeln!(
+        ll,
+        "  %q.{0}{3} = call {1}* @at(i64 %h.{0}, i64 {2}, i64 {0})",
+        pc, cell, offset, suffix
+    )
+    .unwrap();
+}
+
+fn update(ll: &mut String, cell: &str, pc: usize, operation: &str, operand: i64) {
+    writeln!(ll, "  %w.{0} = load {1}, {1}* %q.{0}", pc, cell).unwrap();
+    writeln!(
+        ll,
+        "  %r.{0} = {2} {1} %w.{0}, {3}",
+        pc, cell, operation, operand
+    )
+    .unwrap();
+    writeln!(ll, "  store {1} %r.{0}, {1}* %q.{0}", pc, cell).unwrap();
+}
+
+fn lower<C: Cell>(ll: &mut String, cell: &str, pc: usize, instruction: &Instruction) {
+    match instruction {
+        Instruction::NOP => (),
+
+        Instruction::Left(a) | Instruction::Right(a) => {
+            let function = match instruction {
+                Instruction::Left(_) => "left",
+                _ => "right",
+            };
+
+            writeln!(ll, "  %h.{} = load i64, i64* %head", pc).unwrap();
+            writeln!(
+                ll,
+                "  %m.{0} = call i64 @{1}(i64 %h.{0}, i64 {2}, i64 {0})",
+                pc, function, a
+            )
+            .unwrap();
+            writeln!(ll, "  store i64 %m.{}, i64* %head", pc).unwrap();
+        }
+
+        Instruction::Add(a) | Instruction::Sub(a) => {
+            let operation = match instruction {
+                Instruction::Add(_) => "add",
+                _ => "sub",
+            };
+
+            current(ll, cell, pc);
+            writeln!(
+                ll,
+                "  %r.{0} = {2} {1} %v.{0}, {3}",
+                pc,
+                cell,
+                operation,
+                constant::<C>(*a as u64)
+            )
+            .unwrap();
+            writeln!(ll, "  store {1} %r.{0}, {1}* %p.{0}", pc, cell).unwrap();
+        }
+
+        Instruction::Input(times) => {
+            current(ll, cell, pc);
+            writeln!(
+                ll,
+                "  call void @input({1}* %p.{0}, i64 {2}, i64 {0}, i64 %h.{0})",
+                pc, cell, times
+            )
+            .unwrap();
+        }
+
+        Instruction::Output(times) => {
+            current(ll, cell, pc);
+            writeln!(
+                ll,
+                "  call void @output({1} %v.{0}, i64 {2}, i64 {0}, i64 %h.{0})",
+                pc, cell, times
+            )
+            .unwrap();
+        }
+
+        Instruction::Open(close) => {
+            writeln!(ll, "  br label %open.{}", pc).unwrap();
+            writeln!(ll).unwrap();
+            writeln!(ll, "open.{}:", pc).unwrap();
+            current(ll, cell, pc);
+            writeln!(ll, "  %z.{0} = icmp eq {1} %v.{0}, 0", pc, cell).unwrap();
+            writeln!(
+                ll,
+                "  br i1 %z.{0}, label %end.{1}, label %body.{0}",
+                pc, close
+            )
+            .unwrap();
+            writeln!(ll).unwrap();
+            writeln!(ll, "body.{}:", pc).unwrap();
+        }
+
+        Instruction::Close(open) => {
+            current(ll, cell, pc);
+            writeln!(ll, "  %z.{0} = icmp eq {1} %v.{0}, 0", pc, cell).unwrap();
+            writeln!(
+                ll,
+                "  br i1 %z.{0}, label %end.{0}, label %body.{1}",
+                pc, open
+            )
+            .unwrap();
+            writeln!(ll).unwrap();
+            writeln!(ll, "end.{}:", pc).unwrap();
+        }
+
+        Instruction::Zero => {
+            current(ll, cell, pc);
+            writeln!(ll, "  store {1} 0, {1}* %p.{0}", pc, cell).unwrap();
+        }
+
+        Instruction::FindZeroLeft(a) | Instruction::FindZeroRight(a) => {
+            let function = match instruction {
+                Instruction::FindZeroLeft(_) => "left",
+                _ => "right",
+            };
+
+            writeln!(ll, "  br label %scan.{}", pc).unwrap();
+            writeln!(ll).unwrap();
+            writeln!(ll, "scan.{}:", pc).unwrap();
+            current(ll, cell, pc);
+            writeln!(ll, "  %z.{0} = icmp eq {1} %v.{0}, 0", pc, cell).unwrap();
+            writeln!(ll, "  br i1 %z.{0}, label %found.{0}, label %step.{0}", pc).unwrap();
+            writeln!(ll).unwrap();
+            writeln!(ll, "step.{}:", pc).unwrap();
+            writeln!(
+                ll,
+                "  %m.{0} = call i64 @{1}(i64 %h.{0}, i64 {2}, i64 {0})",
+                pc, function, a
+            )
+            .unwrap();
+            writeln!(ll, "  store i64 %m.{}, i64* %head", pc).unwrap();
+            writeln!(ll, "  br label %scan.{}", pc).unwrap();
+            writeln!(ll).unwrap();
+            writeln!(ll, "found.{}:", pc).unwrap();
+        }
+
+        Instruction::ZeroAddLeft(a) => multiply_add::<C>(ll, cell, pc, &[(-(*a as isize), 1)]),
+
+        Instruction::ZeroAddRight(a) => multiply_add::<C>(ll, cell, pc, &[(*a as isize, 1)]),
+
+        Instruction::MulAdd(pairs) => multiply_add::<C>(ll, cell, pc, pairs),
+
+        Instruction::AddAt(o, a) | Instruction::SubAt(o, a) => {
+            let operation = match instruction {
+                Instruction::AddAt(..) => "add",
+                _ => "sub",
+            };
+
+            writeln!(ll, "  %h.{} = load i64, i64* %head", pc).unwrap();
+            offset(ll, cell, pc, *o, "");
+            update(ll, cell, pc, operation, constant::<C>(*a as u64));
+        }
+
+        Instruction::InputAt(o, times) => {
+            writeln!(ll, "  %h.{} = load i64, i64* %head", pc).unwrap();
+            offset(ll, cell, pc, *o, "");
+            writeln!(ll, "  %i.{0} = add i64 %h.{0}, {1}", pc, o).unwrap();
+            writeln!(
+                ll,
+                "  call void @input({1}* %q.{0}, i64 {2}, i64 {0}, i64 %i.{0})",
+                pc, cell, times
+            )
+            .unwrap();
+        }
+
+        Instruction::OutputAt(o, times) => {
+            writeln!(ll, "  %h.{} = load i64, i64* %head", pc).unwrap();
+            offset(ll, cell, pc, *o, "");
+            writeln!(ll, "  %w.{0} = load {1}, {1}* %q.{0}", pc, cell).unwrap();
+            writeln!(ll, "  %i.{0} = add i64 %h.{0}, {1}", pc, o).unwrap();
+            writeln!(
+                ll,
+                "  call void @output({1} %w.{0}, i64 {2}, i64 {0}, i64 %i.{0})",
+                pc, cell, times
+            )
+            .unwrap();
+        }
+
+        Instruction::ZeroAt(o) => {
+            writeln!(ll, "  %h.{} = load i64, i64* %head", pc).unwrap();
+            offset(ll, cell, pc, *o, "");
+            writeln!(ll, "  store {1} 0, {1}* %q.{0}", pc, cell).unwrap();
+        }
+
+        Instruction::Set(n) => {
+            current(ll, cell, pc);
+            writeln!(
+                ll,
+                "  store {1} {2}, {1}* %p.{0}",
+                pc,
+                cell,
+                constant::<C>(*n as u64)
+            )
+            .unwrap();
+        }
+
+        Instruction::SetAt(o, n) => {
+            writeln!(ll, "  %h.{} = load i64, i64* %head", pc).unwrap();
+            offset(ll, cell, pc, *o, "");
+            writeln!(
+                ll,
+                "  store {1} {2}, {1}* %q.{0}",
+                pc,
+                cell,
+                constant::<C>(*n as u64)
+            )
+            .unwrap();
+        }
+    }
+}
+
+// the current cell is looked up again at the end because the tape may have moved
+fn multiply_add<C: Cell>(ll: &mut String, cell: &str, pc: usize, pairs: &[(isize, isize)]) {
+    current(ll, cell, pc);
+    writeln!(ll, "  %z.{0} = icmp eq {1} %v.{0}, 0", pc, cell).unwrap();
+    writeln!(
+        ll,
+        "  br i1 %z.{0}, label %done.{0}, label %multiply.{0}",
+        pc
+    )
+    .unwrap();
+    writeln!(ll).unwrap();
+    writeln!(ll, "multiply.{}:", pc).unwrap();
+
+    for (k, (o, factor)) in pairs.iter().enumerate() {
+        let suffix = format!(".{}", k);
+
+        offset(ll, cell, pc, *o, &suffix);
+        writeln!(ll, "  %w.{0}.{2} = load {1}, {1}* %q.{0}.{2}", pc, cell, k).unwrap();
+        writeln!(
+            ll,
+            "  %f.{0}.{2} = mul {1} %v.{0}, {3}",
+            pc,
+            cell,
+            k,
+            constant::<C>(*factor as u64)
+        )
+        .unwrap();
+        writeln!(
+            ll,
+            "  %r.{0}.{2} = add {1} %w.{0}.{2}, %f.{0}.{2}",
+            pc, cell, k
+        )
+        .unwrap();
+        writeln!(ll, "  store {1} %r.{0}.{2}, {1}* %q.{0}.{2}", pc, cell, k).unwrap();
+    }
+
+    writeln!(
+        ll,
+        "  %c.{0} = call {1}* @at(i64 %h.{0}, i64 0, i64 {0})",
+        pc, cell
+    )
+    .unwrap();
+    writeln!(ll, "  store {1} 0, {1}* %c.{0}", pc, cell).unwrap();
+    writeln!(ll, "  br label %done.{}", pc).unwrap();
+    writeln!(ll).unwrap();
+    writeln!(ll, "done.{}:", pc).unwrap();
+}
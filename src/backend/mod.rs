@@ -0,0 +1,45 @@
+mod c;
+mod elf;
+mod llvm;
+mod rust;
+mod wasm;
+pub(crate) mod x86_64;
+
+use crate::virtual_machine::{Cell, EofBehavior, TapeSize};
+pub use c::emit_c;
+pub use elf::emit_elf;
+pub use llvm::emit_llvm;
+pub use rust::{emit_rust, emit_rust_function};
+pub use wasm::{emit_wasm, emit_wat};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Target {
+    eof: EofBehavior,
+    tape: TapeSize,
+}
+
+impl Target {
+    pub fn with_eof_behavior(mut self, eof: EofBehavior) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    pub fn with_tape_size(mut self, tape: TapeSize) -> Self {
+        assert!(tape.limit() > 0, "the tape needs at least one cell");
+        self.tape = tape;
+        self
+    }
+
+    pub fn eof_behavior(&self) -> EofBehavior {
+        self.eof
+    }
+
+    pub fn tape_size(&self) -> TapeSize {
+        self.tape
+    }
+}
+
+// constants are reduced to the cell width so they can be written unsigned
+fn wrap<C: Cell>(value: u64) -> u64 {
+    C::from_u64(value).to_u64()
+}
@@ -0,0 +1,342 @@
+use std::fmt::Write;
+
+use super::{wrap, Target};
+use crate::ir::{Instruction, IR};
+use crate::virtual_machine::{Cell, EofBehavior};
+
+// the standalone program brings its own copy of the errors of the vm
+const PROGRAM: &str = r#"use std::fmt;
+use std::io::{self, Write};
+use std::process;
+
+#[derive(Debug)]
+enum RuntimeError {
+    PointerUnderflow(usize, usize),
+    PointerOverflow(usize, usize),
+    Io(usize, usize, io::Error),
+    UnexpectedEof(usize, usize),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PointerUnderflow(pc, head) => write!(
+                f,
+                "pointer moved left of the first cell (pc {}, head {})",
+                pc, head
+            ),
+            Self::PointerOverflow(pc, head) => write!(
+                f,
+                "pointer moved past the end of the tape (pc {}, head {})",
+                pc, head
+            ),
+            Self::Io(pc, head, error) => {
+                write!(f, "i/o error: {} (pc {}, head {})", error, pc, head)
+            }
+            Self::UnexpectedEof(pc, head) => {
+                write!(f, "unexpected end of input (pc {}, head {})", pc, head)
+            }
+        }
+    }
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut writer = io::BufWriter::new(stdout.lock());
+
+    match run(&mut stdin.lock(), &mut writer) {
+        Ok(()) => {
+            if writer.flush().is_err() {
+                process::exit(74);
+            }
+        }
+        Err(error) => {
+            let _ = writer.flush();
+            eprintln!("runtime error: {}", error);
+            process::exit(match error {
+                RuntimeError::Io(..) => 74,
+                _ => 70,
+            });
+        }
+    }
+}
+"#;
+
+// the helpers are specialized for the eof behavior of the target
+fn helpers(eof: &str) -> String {
+    format!(
+        r#"
+    fn left(head: usize, a: usize, pc: usize) -> Result<usize, RuntimeError> {{
+        head.checked_sub(a)
+            .ok_or(RuntimeError::PointerUnderflow(pc, head))
+    }}
+
+    fn right(
+        tape: &mut Vec<Cell>,
+        head: usize,
+        a: usize,
+        pc: usize,
+    ) -> Result<usize, RuntimeError> {{
+        match head.checked_add(a) {{
+            Some(index) if index < LIMIT => {{
+                if index >= tape.len() {{
+                    let length = tape.len().saturating_mul(2).max(index + 1).min(LIMIT);
+                    tape.resize(length, 0);
+                }}
+                Ok(index)
+            }}
+            _ => Err(RuntimeError::PointerOverflow(pc, head)),
+        }}
+    }}
+
+    fn at(
+        tape: &mut Vec<Cell>,
+        head: usize,
+        offset: isize,
+        pc: usize,
+    ) -> Result<usize, RuntimeError> {{
+        if offset < 0 {{
+            left(head, offset.unsigned_abs(), pc)
+        }} else {{
+            right(tape, head, offset as usize, pc)
+        }}
+    }}
+
+    fn io_error(error: std::io::Error, pc: usize, head: usize) -> RuntimeError {{
+        match error.kind() {{
+            std::io::ErrorKind::UnexpectedEof => RuntimeError::UnexpectedEof(pc, head),
+            _ => RuntimeError::Io(pc, head, error),
+        }}
+    }}
+
+    fn input(
+        reader: &mut dyn std::io::Read,
+        writer: &mut dyn std::io::Write,
+        cell: &mut Cell,
+        times: usize,
+        pc: usize,
+        head: usize,
+    ) -> Result<(), RuntimeError> {{
+        for _ in 0..times {{
+            let mut buffer = [0; 1];
+
+            writer.flush().map_err(|e| io_error(e, pc, head))?;
+            match reader.read_exact(&mut buffer) {{
+                Ok(_) => *cell = buffer[0] as Cell,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {eof},
+                Err(e) => return Err(io_error(e, pc, head)),
+            }}
+        }}
+        Ok(())
+    }}
+
+    fn output(
+        writer: &mut dyn std::io::Write,
+        value: Cell,
+        times: usize,
+        pc: usize,
+        head: usize,
+    ) -> Result<(), RuntimeError> {{
+        for _ in 0..times {{
+            writer
+                .write_all(&[value as u8])
+                .map_err(|e| io_error(e, pc, head))?;
+        }}
+        Ok(())
+    }}
+"#,
+        eof = eof
+    )
+}
+
+// a function that runs the program like the vm does, it needs a RuntimeError
+// with the variants of the one in virtual_machine in scope
+pub fn emit_rust_function<C: Cell>(ir: &IR, target: &Target) -> String {
+    let mut rust = String::new();
+    let tape = target.tape_size();
+
+    writeln!(rust, "#[allow(unused, clippy::all)]").unwrap();
+    writeln!(rust, "fn run(").unwrap();
+    writeln!(rust, "    reader: &mut dyn std::io::Read,").unwrap();
+    writeln!(rust, "    writer: &mut dyn std::io::Write,").unwrap();
+    writeln!(rust, ") -> Result<(), RuntimeError> {{").unwrap();
+    writeln!(rust, "    type Cell = u{};", C::BITS).unwrap();
+    writeln!(rust).unwrap();
+    writeln!(
+        rust,
+        "    const INITIAL_LENGTH: usize = {};",
+        tape.initial_length()
+    )
+    .unwrap();
+
+    match tape.limit() {
+        usize::MAX => writeln!(rust, "    const LIMIT: usize = usize::MAX;").unwrap(),
+        limit => writeln!(rust, "    const LIMIT: usize = {};", limit).unwrap(),
+    }
+
+    rust.push_str(&helpers(match target.eof_behavior() {
+        EofBehavior::Error => "return Err(io_error(e, pc, head))",
+        EofBehavior::Unchanged => "()",
+        EofBehavior::Zero => "*cell = 0",
+        EofBehavior::MinusOne => "*cell = Cell::MAX",
+    }));
+    writeln!(rust).unwrap();
+    writeln!(
+        rust,
+        "    let mut tape: Vec<Cell> = vec![0; INITIAL_LENGTH];"
+    )
+    .unwrap();
+    writeln!(rust, "    let mut head: usize = 0;").unwrap();
+    writeln!(rust).unwrap();
+
+    let mut depth = 1;
+
+    for (pc, instruction) in ir.vec().iter().enumerate() {
+        if let Instruction::Close(_) = instruction {
+            depth -= 1;
+        }
+
+        let indent = "    ".repeat(depth);
+
+        for line in statement::<C>(pc, instruction).lines() {
+            if line.is_empty() {
+                writeln!(rust).unwrap();
+            } else {
+                writeln!(rust, "{}{}", indent, line).unwrap();
+            }
+        }
+
+        if let Instruction::Open(_) = instruction {
+            depth += 1;
+        }
+    }
+
+    writeln!(rust).unwrap();
+    writeln!(rust, "    Ok(())").unwrap();
+    writeln!(rust, "}}").unwrap();
+
+    rust
+}
+
+// a standalone program that reads stdin and writes stdout like `run` does
+pub fn emit_rust<C: Cell>(ir: &IR, target: &Target) -> String {
+    format!("{}\n{}", PROGRAM, emit_rust_function::<C>(ir, target))
+}
+
+fn statement<C: Cell>(pc: usize, instruction: &Instruction) -> String {
+    let constant = |value: u64| wrap::<C>(value);
+    let at = |offset: isize| format!("let target = at(&mut tape, head, {}, {})?;\n", offset, pc);
+
+    match instruction {
+        Instruction::NOP => String::new(),
+
+        Instruction::Left(a) => format!("head = left(head, {}, {})?;", a, pc),
+
+        Instruction::Right(a) => format!("head = right(&mut tape, head, {}, {})?;", a, pc),
+
+        Instruction::Add(a) => format!(
+            "tape[head] = tape[head].wrapping_add({});",
+            constant(*a as u64)
+        ),
+
+        Instruction::Sub(a) => format!(
+            "tape[head] = tape[head].wrapping_sub({});",
+            constant(*a as u64)
+        ),
+
+        Instruction::Input(times) => format!(
+            "input(reader, writer, &mut tape[head], {}, {}, head)?;",
+            times, pc
+        ),
+
+        Instruction::Output(times) => {
+            format!("output(writer, tape[head], {}, {}, head)?;", times, pc)
+        }
+
+        Instruction::Open(_) => String::from("while tape[head] != 0 {"),
+
+        Instruction::Close(_) => String::from("}"),
+
+        Instruction::Zero => String::from("tape[head] = 0;"),
+
+        Instruction::FindZeroLeft(a) => format!(
+            "while tape[head] != 0 {{\n    head = left(head, {}, {})?;\n}}",
+            a, pc
+        ),
+
+        Instruction::FindZeroRight(a) => format!(
+            "while tape[head] != 0 {{\n    head = right(&mut tape, head, {}, {})?;\n}}",
+            a, pc
+        ),
+
+        Instruction::ZeroAddLeft(a) => multiply_add::<C>(&[(-(*a as isize), 1)], pc),
+
+        Instruction::ZeroAddRight(a) => multiply_add::<C>(&[(*a as isize, 1)], pc),
+
+        Instruction::MulAdd(pairs) => multiply_add::<C>(pairs, pc),
+
+        Instruction::AddAt(offset, a) => format!(
+            "{}tape[target] = tape[target].wrapping_add({});",
+            at(*offset),
+            constant(*a as u64)
+        ),
+
+        Instruction::SubAt(offset, a) => format!(
+            "{}tape[target] = tape[target].wrapping_sub({});",
+            at(*offset),
+            constant(*a as u64)
+        ),
+
+        Instruction::InputAt(offset, times) => format!(
+            "{}input(reader, writer, &mut tape[target], {}, {}, target)?;",
+            at(*offset),
+            times,
+            pc
+        ),
+
+        Instruction::OutputAt(offset, times) => format!(
+            "{}output(writer, tape[target], {}, {}, target)?;",
+            at(*offset),
+            times,
+            pc
+        ),
+
+        Instruction::ZeroAt(offset) => format!("{}tape[target] = 0;", at(*offset)),
+
+        Instruction::Set(n) => format!("tape[head] = {};", constant(*n as u64)),
+
+        Instruction::SetAt(offset, n) => {
+            format!("{}tape[target] = {};", at(*offset), constant(*n as u64))
+        }
+    }
+}
+
+fn multiply_add<C: Cell>(pairs: &[(isize, isize)], pc: usize) -> String {
+    let mut block = String::from("if tape[head] != 0 {\n    let value = tape[head];\n\n");
+
+    for (offset, factor) in pairs {
+        writeln!(
+            block,
+            "    let target = at(&mut tape, head, {}, {})?;",
+            offset, pc
+        )
+        .unwrap();
+
+        match factor {
+            1 => writeln!(
+                block,
+                "    tape[target] = tape[target].wrapping_add(value);"
+            ),
+            _ => writeln!(
+                block,
+                "    tape[target] = tape[target].wrapping_add(value.wrapping_mul({}));",
+                wrap::<C>(*factor as u64)
+            ),
+        }
+        .unwrap();
+    }
+
+    block.push_str("    tape[head] = 0;\n}");
+    block
+}
@@ -0,0 +1,946 @@
+use std::fmt::Write;
+
+use super::{wrap, Target};
+use crate::ir::{Instruction, IR};
+use crate::virtual_machine::{Cell, EofBehavior};
+
+// run returns one of these and leaves the pc and head of an error in the
+// exported globals
+const FINISHED: i32 = 0;
+const POINTER_UNDERFLOW: i32 = 1;
+const POINTER_OVERFLOW: i32 = 2;
+const UNEXPECTED_EOF: i32 = 3;
+
+const PAGE: u64 = 65536;
+
+const READ_BYTE: u32 = 0;
+const WRITE_BYTE: u32 = 1;
+const RESERVE: u32 = 2;
+const RUN: u32 = 3;
+
+// the last cell the memory holds, then the pc and head of an error
+const LAST: u32 = 0;
+const PC: u32 = 1;
+const HEAD: u32 = 2;
+
+// locals of run, the value has the type of a cell
+const HEAD_LOCAL: u32 = 0;
+const TARGET: u32 = 1;
+const VALUE: u32 = 2;
+const BYTE: u32 = 3;
+const COUNT: u32 = 4;
+
+// locals of reserve
+const INDEX: u32 = 0;
+const PAGES: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Type {
+    I32,
+    I64,
+}
+
+impl Type {
+    fn code(self) -> u8 {
+        match self {
+            Self::I32 => 0x7f,
+            Self::I64 => 0x7e,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Block,
+    Loop,
+    If,
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Const(i32),
+    I64Const(i64),
+    I32Eqz,
+    I32Eq,
+    I32LtU,
+    I32GtU,
+    I32GeU,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32And,
+    I32Shl,
+    I64Eqz,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64ShrU,
+    I32WrapI64,
+    I64ExtendI32U,
+    Load(u32),
+    Store(u32),
+    MemorySize,
+    MemoryGrow,
+}
+
+impl Op {
+    fn encode(self, code: &mut Vec<u8>) {
+        match self {
+            Self::Block => code.extend_from_slice(&[0x02, 0x40]),
+            Self::Loop => code.extend_from_slice(&[0x03, 0x40]),
+            Self::If => code.extend_from_slice(&[0x04, 0x40]),
+            Self::Else => code.push(0x05),
+            Self::End => code.push(0x0b),
+            Self::Br(depth) => immediate(code, 0x0c, depth),
+            Self::BrIf(depth) => immediate(code, 0x0d, depth),
+            Self::Return => code.push(0x0f),
+            Self::Call(function) => immediate(code, 0x10, function),
+            Self::Select => code.push(0x1b),
+            Self::LocalGet(local) => immediate(code, 0x20, local),
+            Self::LocalSet(local) => immediate(code, 0x21, local),
+            Self::LocalTee(local) => immediate(code, 0x22, local),
+            Self::GlobalGet(global) => immediate(code, 0x23, global),
+            Self::GlobalSet(global) => immediate(code, 0x24, global),
+            Self::I32Const(value) => {
+                code.push(0x41);
+                signed(code, value as i64);
+            }
+            Self::I64Const(value) => {
+                code.push(0x42);
+                signed(code, value);
+            }
+            Self::I32Eqz => code.push(0x45),
+            Self::I32Eq => code.push(0x46),
+            Self::I32LtU => code.push(0x49),
+            Self::I32GtU => code.push(0x4b),
+            Self::I32GeU => code.push(0x4f),
+            Self::I64Eqz => code.push(0x50),
+            Self::I32Add => code.push(0x6a),
+            Self::I32Sub => code.push(0x6b),
+            Self::I32Mul => code.push(0x6c),
+            Self::I32And => code.push(0x71),
+            Self::I32Shl => code.push(0x74),
+            Self::I64Add => code.push(0x7c),
+            Self::I64Sub => code.push(0x7d),
+            Self::I64Mul => code.push(0x7e),
+            Self::I64ShrU => code.push(0x88),
+            Self::I32WrapI64 => code.push(0xa7),
+            Self::I64ExtendI32U => code.push(0xad),
+            Self::Load(bits) | Self::Store(bits) => {
+                let opcode = match (self, bits) {
+                    (Self::Load(_), 8) => 0x2d,
+                    (Self::Load(_), 16) => 0x2f,
+                    (Self::Load(_), 32) => 0x28,
+                    (Self::Load(_), _) => 0x29,
+                    (_, 8) => 0x3a,
+                    (_, 16) => 0x3b,
+                    (_, 32) => 0x36,
+                    _ => 0x37,
+                };
+
+                // natural alignment and no offset
+                code.push(opcode);
+                unsigned(code, (bits / 8).trailing_zeros() as u64);
+                unsigned(code, 0);
+            }
+            Self::MemorySize => code.extend_from_slice(&[0x3f, 0x00]),
+            Self::MemoryGrow => code.extend_from_slice(&[0x40, 0x00]),
+        }
+    }
+
+    fn text(self) -> String {
+        match self {
+            Self::Block => String::from("block"),
+            Self::Loop => String::from("loop"),
+            Self::If => String::from("if"),
+            Self::Else => String::from("else"),
+            Self::End => String::from("end"),
+            Self::Br(depth) => format!("br {}", depth),
+            Self::BrIf(depth) => format!("br_if {}", depth),
+            Self::Return => String::from("return"),
+            Self::Call(function) => format!("call {}", function),
+            Self::Select => String::from("select"),
+            Self::LocalGet(local) => format!("local.get {}", local),
+            Self::LocalSet(local) => format!("local.set {}", local),
+            Self::LocalTee(local) => format!("local.tee {}", local),
+            Self::GlobalGet(global) => format!("global.get {}", global),
+            Self::GlobalSet(global) => format!("global.set {}", global),
+            Self::I32Const(value) => format!("i32.const {}", value),
+            Self::I64Const(value) => format!("i64.const {}", value),
+            Self::I32Eqz => String::from("i32.eqz"),
+            Self::I32Eq => String::from("i32.eq"),
+            Self::I32LtU => String::from("i32.lt_u"),
+            Self::I32GtU => String::from("i32.gt_u"),
+            Self::I32GeU => String::from("i32.ge_u"),
+            Self::I64Eqz => String::from("i64.eqz"),
+            Self::I32Add => String::from("i32.add"),
+            Self::I32Sub => String::from("i32.sub"),
+            Self::I32Mul => String::from("i32.mul"),
+            Self::I32And => String::from("i32.and"),
+            Self::I32Shl => String::from("i32.shl"),
+            Self::I64Add => String::from("i64.add"),
+            Self::I64Sub => String::from("i64.sub"),
+            Self::I64Mul => String::from("i64.mul"),
+            Self::I64ShrU => String::from("i64.shr_u"),
+            Self::I32WrapI64 => String::from("i32.wrap_i64"),
+            Self::I64ExtendI32U => String::from("i64.extend_i32_u"),
+            Self::Load(8) => String::from("i32.load8_u"),
+            Self::Load(16) => String::from("i32.load16_u"),
+            Self::Load(32) => String::from("i32.load"),
+            Self::Load(_) => String::from("i64.load"),
+            Self::Store(8) => String::from("i32.store8"),
+            Self::Store(16) => String::from("i32.store16"),
+            Self::Store(32) => String::from("i32.store"),
+            Self::Store(_) => String::from("i64.store"),
+            Self::MemorySize => String::from("memory.size"),
+            Self::MemoryGrow => String::from("memory.grow"),
+        }
+    }
+}
+
+fn unsigned(code: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            code.push(byte);
+            return;
+        }
+
+        code.push(byte | 0x80);
+    }
+}
+
+fn signed(code: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            code.push(byte);
+            return;
+        }
+
+        code.push(byte | 0x80);
+    }
+}
+
+fn immediate(code: &mut Vec<u8>, opcode: u8, value: u32) {
+    code.push(opcode);
+    unsigned(code, value as u64);
+}
+
+fn name(code: &mut Vec<u8>, name: &str) {
+    unsigned(code, name.len() as u64);
+    code.extend_from_slice(name.as_bytes());
+}
+
+// a comment in the text format, nothing in the binary one
+enum Line {
+    Op(Op),
+    Comment(String),
+}
+
+struct Function {
+    parameters: Vec<Type>,
+    results: Vec<Type>,
+    locals: Vec<Type>,
+    body: Vec<Line>,
+}
+
+struct Module {
+    pages: u64,
+    last: u32,
+    functions: Vec<Function>,
+}
+
+struct Lowering<C: Cell> {
+    body: Vec<Line>,
+    limit: u64,
+    eof: EofBehavior,
+    cell: std::marker::PhantomData<C>,
+}
+
+impl<C: Cell> Lowering<C> {
+    fn op(&mut self, op: Op) {
+        self.body.push(Line::Op(op));
+    }
+
+    fn ops(&mut self, ops: &[Op]) {
+        for op in ops {
+            self.op(*op);
+        }
+    }
+
+    fn wide(&self) -> bool {
+        C::BITS == 64
+    }
+
+    fn constant(&self, value: u64) -> Op {
+        let value = wrap::<C>(value);
+
+        if self.wide() {
+            Op::I64Const(value as i64)
+        } else {
+            Op::I32Const(value as u32 as i32)
+        }
+    }
+
+    fn address(&mut self, local: u32) {
+        self.op(Op::LocalGet(local));
+
+        if C::BITS > 8 {
+            let shift = (C::BITS / 8).trailing_zeros() as i32;
+            self.ops(&[Op::I32Const(shift), Op::I32Shl]);
+        }
+    }
+
+    fn load(&mut self, local: u32) {
+        self.address(local);
+        self.op(Op::Load(C::BITS));
+    }
+
+    // leaves whether the value on the stack is zero
+    fn is_zero(&mut self) {
+        self.op(if self.wide() { Op::I64Eqz } else { Op::I32Eqz });
+    }
+
+    fn fail(&mut self, status: i32, pc: usize) {
+        self.fail_at(status, pc, HEAD_LOCAL);
+    }
+
+    // reports the cell in the local as the head, for errors on offset cells
+    fn fail_at(&mut self, status: i32, pc: usize, local: u32) {
+        self.ops(&[
+            Op::I32Const(pc as u32 as i32),
+            Op::GlobalSet(PC),
+            Op::LocalGet(local),
+            Op::GlobalSet(HEAD),
+            Op::I32Const(status),
+            Op::Return,
+        ]);
+    }
+
+    fn check_left(&mut self, a: usize, pc: usize) {
+        if a > u32::MAX as usize {
+            return self.fail(POINTER_UNDERFLOW, pc);
+        }
+
+        self.ops(&[
+            Op::LocalGet(HEAD_LOCAL),
+            Op::I32Const(a as u32 as i32),
+            Op::I32LtU,
+            Op::If,
+        ]);
+        self.fail(POINTER_UNDERFLOW, pc);
+        self.op(Op::End);
+    }
+
+    // leaves the index of the cell at the offset from the head in the target
+    fn offset(&mut self, offset: isize, pc: usize) {
+        if offset < 0 {
+            let a = offset.unsigned_abs();
+
+            self.check_left(a, pc);
+            self.ops(&[
+                Op::LocalGet(HEAD_LOCAL),
+                Op::I32Const(a as u32 as i32),
+                Op::I32Sub,
+                Op::LocalSet(TARGET),
+            ]);
+            return;
+        }
+
+        let a = offset as u64;
+
+        if a >= self.limit {
+            return self.fail(POINTER_OVERFLOW, pc);
+        }
+
+        if self.limit - a <= u32::MAX as u64 {
+            self.ops(&[
+                Op::LocalGet(HEAD_LOCAL),
+                Op::I32Const((self.limit - a) as u32 as i32),
+                Op::I32GeU,
+                Op::If,
+            ]);
+            self.fail(POINTER_OVERFLOW, pc);
+            self.op(Op::End);
+        }
+
+        self.ops(&[
+            Op::LocalGet(HEAD_LOCAL),
+            Op::I32Const(a as u32 as i32),
+            Op::I32Add,
+            Op::LocalTee(TARGET),
+            Op::GlobalGet(LAST),
+            Op::I32GtU,
+            Op::If,
+            Op::LocalGet(TARGET),
+            Op::Call(RESERVE),
+            Op::I32Eqz,
+            Op::If,
+        ]);
+        self.fail(POINTER_OVERFLOW, pc);
+        self.ops(&[Op::End, Op::End]);
+    }
+
+    fn repeat(&mut self, times: usize, body: impl Fn(&mut Self)) {
+        if times == 1 {
+            return body(self);
+        }
+
+        self.ops(&[
+            Op::I32Const(times as u32 as i32),
+            Op::LocalSet(COUNT),
+            Op::Loop,
+        ]);
+        body(self);
+        self.ops(&[
+            Op::LocalGet(COUNT),
+            Op::I32Const(1),
+            Op::I32Sub,
+            Op::LocalTee(COUNT),
+            Op::BrIf(0),
+            Op::End,
+        ]);
+    }
+
+    fn input(&mut self, local: u32, times: usize, pc: usize) {
+        self.repeat(times, |lowering| {
+            lowering.ops(&[
+                Op::Call(READ_BYTE),
+                Op::LocalTee(BYTE),
+                Op::I32Const(-1),
+                Op::I32Eq,
+                Op::If,
+            ]);
+
+            match lowering.eof {
+                EofBehavior::Error => lowering.fail_at(UNEXPECTED_EOF, pc, local),
+                EofBehavior::Unchanged => (),
+                EofBehavior::Zero | EofBehavior::MinusOne => {
+                    let value = match lowering.eof {
+                        EofBehavior::Zero => 0,
+                        _ => u64::MAX,
+                    };
+
+                    lowering.address(local);
+                    lowering.op(lowering.constant(value));
+                    lowering.op(Op::Store(C::BITS));
+                }
+            }
+
+            lowering.op(Op::Else);
+            lowering.address(local);
+            lowering.op(Op::LocalGet(BYTE));
+
+            if lowering.wide() {
+                lowering.op(Op::I64ExtendI32U);
+            }
+
+            lowering.ops(&[Op::Store(C::BITS), Op::End]);
+        });
+    }
+
+    fn output(&mut self, local: u32, times: usize) {
+        self.repeat(times, |lowering| {
+            lowering.load(local);
+
+            if lowering.wide() {
+                lowering.op(Op::I32WrapI64);
+            }
+
+            lowering.ops(&[Op::I32Const(255), Op::I32And, Op::Call(WRITE_BYTE)]);
+        });
+    }
+
+    fn update(&mut self, local: u32, operation: Op, value: u64) {
+        self.address(local);
+        self.load(local);
+        self.op(self.constant(value));
+        self.op(operation);
+        self.op(Op::Store(C::BITS));
+    }
+
+    fn set(&mut self, local: u32, value: u64) {
+        self.address(local);
+        self.op(self.constant(value));
+        self.op(Op::Store(C::BITS));
+    }
+
+    fn add(&self) -> Op {
+        if self.wide() {
+            Op::I64Add
+        } else {
+            Op::I32Add
+        }
+    }
+
+    fn sub(&self) -> Op {
+        if self.wide() {
+            Op::I64Sub
+        } else {
+            Op::I32Sub
+        }
+    }
+
+    fn multiply_add(&mut self, pairs: &[(isize, isize)], pc: usize) {
+        self.load(HEAD_LOCAL);
+        self.op(Op::LocalTee(VALUE));
+        self.is_zero();
+        self.ops(&[Op::I32Eqz, Op::If]);
+
+        for (offset, factor) in pairs {
+            self.offset(*offset, pc);
+            self.address(TARGET);
+            self.load(TARGET);
+            self.op(Op::LocalGet(VALUE));
+
+            if *factor != 1 {
+                self.op(self.constant(*factor as u64));
+                self.op(if self.wide() { Op::I64Mul } else { Op::I32Mul });
+            }
+
+            self.op(self.add());
+            self.op(Op::Store(C::BITS));
+        }
+
+        self.set(HEAD_LOCAL, 0);
+        self.op(Op::End);
+    }
+
+    fn instruction(&mut self, pc: usize, instruction: &Instruction) {
+        if let Instruction::NOP = instruction {
+            return;
+        }
+
+        self.body
+            .push(Line::Comment(format!("pc {}: {}", pc, instruction)));
+
+        match instruction {
+            Instruction::NOP => (),
+
+            Instruction::Left(a) => {
+                self.check_left(*a, pc);
+                self.ops(&[
+                    Op::LocalGet(HEAD_LOCAL),
+                    Op::I32Const(*a as u32 as i32),
+                    Op::I32Sub,
+                    Op::LocalSet(HEAD_LOCAL),
+                ]);
+            }
+
+            Instruction::Right(a) => {
+                self.offset(*a as isize, pc);
+                self.ops(&[Op::LocalGet(TARGET), Op::LocalSet(HEAD_LOCAL)]);
+            }
+
+            Instruction::Add(a) => self.update(HEAD_LOCAL, self.add(), *a as u64),
+
+            Instruction::Sub(a) => self.update(HEAD_LOCAL, self.sub(), *a as u64),
+
+            Instruction::Input(times) => self.input(HEAD_LOCAL, *times, pc),
+
+            Instruction::Output(times) => self.output(HEAD_LOCAL, *times),
+
+            Instruction::Open(_) => {
+                self.op(Op::Block);
+                self.load(HEAD_LOCAL);
+                self.is_zero();
+                self.ops(&[Op::BrIf(0), Op::Loop]);
+            }
+
+            Instruction::Close(_) => {
+                self.load(HEAD_LOCAL);
+                self.is_zero();
+                self.ops(&[Op::I32Eqz, Op::BrIf(0), Op::End, Op::End]);
+            }
+
+            Instruction::Zero => self.set(HEAD_LOCAL, 0),
+
+            Instruction::FindZeroLeft(a) | Instruction::FindZeroRight(a) => {
+                self.ops(&[Op::Block, Op::Loop]);
+                self.load(HEAD_LOCAL);
+                self.is_zero();
+                self.op(Op::BrIf(1));
+
+                if let Instruction::FindZeroLeft(_) = instruction {
+                    self.check_left(*a, pc);
+                    self.ops(&[
+                        Op::LocalGet(HEAD_LOCAL),
+                        Op::I32Const(*a as u32 as i32),
+                        Op::I32Sub,
+                        Op::LocalSet(HEAD_LOCAL),
+                    ]);
+                } else {
+                    self.offset(*a as isize, pc);
+                    self.ops(&[Op::LocalGet(TARGET), Op::LocalSet(HEAD_LOCAL)]);
+                }
+
+                self.ops(&[Op::Br(0), Op::End, Op::End]);
+            }
+
+            Instruction::ZeroAddLeft(a) => self.multiply_add(&[(-(*a as isize), 1)], pc),
+
+            Instruction::ZeroAddRight(a) => self.multiply_add(&[(*a as isize, 1)], pc),
+
+            Instruction::MulAdd(pairs) => self.multiply_add(pairs, pc),
+
+            Instruction::AddAt(offset, a) | Instruction::SubAt(offset, a) => {
+                let operation = match instruction {
+                    Instruction::AddAt(..) => self.add(),
+                    _ => self.sub(),
+                };
+
+                self.offset(*offset, pc);
+                self.update(TARGET, operation, *a as u64);
+            }
+
+            Instruction::InputAt(offset, times) => {
+                self.offset(*offset, pc);
+                self.input(TARGET, *times, pc);
+            }
+
+            Instruction::OutputAt(offset, times) => {
+                self.offset(*offset, pc);
+                self.output(TARGET, *times);
+            }
+
+            Instruction::ZeroAt(offset) => {
+                self.offset(*offset, pc);
+                self.set(TARGET, 0);
+            }
+
+            Instruction::Set(n) => self.set(HEAD_LOCAL, *n as u64),
+
+            Instruction::SetAt(offset, n) => {
+                self.offset(*offset, pc);
+                self.set(TARGET, *n as u64);
+            }
+        }
+    }
+}
+
+fn module<C: Cell>(ir: &IR, target: &Target) -> Module {
+    let size = C::BITS as u64 / 8;
+    let shift = (C::BITS / 8).trailing_zeros() as i32;
+    let tape = target.tape_size();
+
+    // the memory holds whole pages, so the tape starts with every cell of them
+    let pages = (tape.initial_length() as u64 * size).div_ceil(PAGE);
+    let last = (pages * PAGE / size).wrapping_sub(1) as u32;
+
+    let mut lowering = Lowering::<C> {
+        body: Vec::new(),
+        limit: (tape.limit() as u64).min((u32::MAX as u64 + 1) / size),
+        eof: target.eof_behavior(),
+        cell: std::marker::PhantomData,
+    };
+
+    for (pc, instruction) in ir.vec().iter().enumerate() {
+        lowering.instruction(pc, instruction);
+    }
+
+    lowering.op(Op::I32Const(FINISHED));
+
+    // grows the memory so the index is on it, at least doubling it like the
+    // vm doubles its tape, and returns whether it could
+    let reserve = vec![
+        Op::LocalGet(INDEX),
+        Op::I64ExtendI32U,
+        Op::I64Const(1),
+        Op::I64Add,
+        Op::I64Const(size as i64),
+        Op::I64Mul,
+        Op::I64Const(PAGE as i64 - 1),
+        Op::I64Add,
+        Op::I64Const(16),
+        Op::I64ShrU,
+        Op::I32WrapI64,
+        Op::MemorySize,
+        Op::I32Sub,
+        Op::LocalTee(PAGES),
+        Op::MemorySize,
+        Op::LocalGet(PAGES),
+        Op::MemorySize,
+        Op::I32GtU,
+        Op::Select,
+        Op::MemoryGrow,
+        Op::I32Const(-1),
+        Op::I32Eq,
+        Op::If,
+        Op::LocalGet(PAGES),
+        Op::MemoryGrow,
+        Op::I32Const(-1),
+        Op::I32Eq,
+        Op::If,
+        Op::I32Const(0),
+        Op::Return,
+        Op::End,
+        Op::End,
+        Op::MemorySize,
+        Op::I32Const(16 - shift),
+        Op::I32Shl,
+        Op::I32Const(1),
+        Op::I32Sub,
+        Op::GlobalSet(LAST),
+        Op::I32Const(1),
+    ];
+
+    let cell = if C::BITS == 64 { Type::I64 } else { Type::I32 };
+
+    Module {
+        pages,
+        last,
+        functions: vec![
+            Function {
+                parameters: vec![Type::I32],
+                results: vec![Type::I32],
+                locals: vec![Type::I32],
+                body: reserve.into_iter().map(Line::Op).collect(),
+            },
+            Function {
+                parameters: Vec::new(),
+                results: vec![Type::I32],
+                locals: vec![Type::I32, Type::I32, cell, Type::I32, Type::I32],
+                body: lowering.body,
+            },
+        ],
+    }
+}
+
+// the types of read_byte, write_byte and reserve, run shares the first
+const TYPES: &[(&[Type], &[Type])] = &[
+    (&[], &[Type::I32]),
+    (&[Type::I32], &[]),
+    (&[Type::I32], &[Type::I32]),
+];
+
+fn type_index(function: &Function) -> u32 {
+    TYPES
+        .iter()
+        .position(|(parameters, results)| {
+            *parameters == &function.parameters[..] && *results == &function.results[..]
+        })
+        .unwrap() as u32
+}
+
+pub fn emit_wat<C: Cell>(ir: &IR, target: &Target) -> String {
+    let module = module::<C>(ir, target);
+    let mut wat = String::new();
+    let list = |types: &[Type]| types.iter().map(|t| t.name()).collect::<Vec<_>>().join(" ");
+
+    writeln!(wat, "(module").unwrap();
+
+    for (index, (parameters, results)) in TYPES.iter().enumerate() {
+        write!(wat, "  (type (;{};) (func", index).unwrap();
+
+        if !parameters.is_empty() {
+            write!(wat, " (param {})", list(parameters)).unwrap();
+        }
+
+        if !results.is_empty() {
+            write!(wat, " (result {})", list(results)).unwrap();
+        }
+
+        writeln!(wat, "))").unwrap();
+    }
+
+    writeln!(
+        wat,
+        "  (import \"env\" \"read_byte\" (func (;0;) (type 0)))"
+    )
+    .unwrap();
+    writeln!(
+        wat,
+        "  (import \"env\" \"write_byte\" (func (;1;) (type 1)))"
+    )
+    .unwrap();
+
+    for (index, function) in module.functions.iter().enumerate() {
+        let index = index as u32 + RESERVE;
+
+        write!(wat, "  (func (;{};) (type {})", index, type_index(function)).unwrap();
+
+        if !function.parameters.is_empty() {
+            write!(wat, " (param {})", list(&function.parameters)).unwrap();
+        }
+
+        if !function.results.is_empty() {
+            write!(wat, " (result {})", list(&function.results)).unwrap();
+        }
+
+        writeln!(wat).unwrap();
+        writeln!(wat, "    (local {})", list(&function.locals)).unwrap();
+
+        let mut depth = 2;
+
+        for line in &function.body {
+            match line {
+                Line::Comment(comment) => {
+                    writeln!(wat, "{};; {}", "  ".repeat(depth), comment).unwrap()
+                }
+                Line::Op(op) => {
+                    if let Op::Else | Op::End = op {
+                        depth -= 1;
+                    }
+
+                    writeln!(wat, "{}{}", "  ".repeat(depth), op.text()).unwrap();
+
+                    if let Op::Block | Op::Loop | Op::If | Op::Else = op {
+                        depth += 1;
+                    }
+                }
+            }
+        }
+
+        wat.truncate(wat.len() - 1);
+        writeln!(wat, ")").unwrap();
+    }
+
+    writeln!(wat, "  (memory (;0;) {})", module.pages).unwrap();
+    writeln!(
+        wat,
+        "  (global (;0;) (mut i32) (i32.const {}))",
+        module.last as i32
+    )
+    .unwrap();
+    writeln!(wat, "  (global (;1;) (mut i32) (i32.const 0))").unwrap();
+    writeln!(wat, "  (global (;2;) (mut i32) (i32.const 0))").unwrap();
+    writeln!(wat, "  (export \"memory\" (memory 0))").unwrap();
+    writeln!(wat, "  (export \"run\" (func {}))", RUN).unwrap();
+    writeln!(wat, "  (export \"pc\" (global {}))", PC).unwrap();
+    writeln!(wat, "  (export \"head\" (global {})))", HEAD).unwrap();
+
+    wat
+}
+
+pub fn emit_wasm<C: Cell>(ir: &IR, target: &Target) -> Vec<u8> {
+    let module = module::<C>(ir, target);
+    let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+
+    let mut types = Vec::new();
+    unsigned(&mut types, TYPES.len() as u64);
+
+    for (parameters, results) in TYPES {
+        types.push(0x60);
+
+        for list in &[parameters, results] {
+            unsigned(&mut types, list.len() as u64);
+            types.extend(list.iter().map(|t| t.code()));
+        }
+    }
+
+    section(&mut wasm, 1, &types);
+
+    let mut imports = Vec::new();
+    unsigned(&mut imports, 2);
+
+    for (field, index) in &[("read_byte", 0), ("write_byte", 1)] {
+        name(&mut imports, "env");
+        name(&mut imports, field);
+        imports.push(0x00);
+        unsigned(&mut imports, *index);
+    }
+
+    section(&mut wasm, 2, &imports);
+
+    let mut functions = Vec::new();
+    unsigned(&mut functions, module.functions.len() as u64);
+
+    for function in &module.functions {
+        unsigned(&mut functions, type_index(function) as u64);
+    }
+
+    section(&mut wasm, 3, &functions);
+
+    let mut memory = vec![1, 0x00];
+    unsigned(&mut memory, module.pages);
+    section(&mut wasm, 5, &memory);
+
+    let mut globals = Vec::new();
+    unsigned(&mut globals, 3);
+
+    for value in &[module.last as i32, 0, 0] {
+        globals.extend_from_slice(&[Type::I32.code(), 0x01]);
+        Op::I32Const(*value).encode(&mut globals);
+        Op::End.encode(&mut globals);
+    }
+
+    section(&mut wasm, 6, &globals);
+
+    let mut exports = Vec::new();
+    unsigned(&mut exports, 4);
+
+    for (field, kind, index) in &[
+        ("memory", 0x02, 0),
+        ("run", 0x00, RUN),
+        ("pc", 0x03, PC),
+        ("head", 0x03, HEAD),
+    ] {
+        name(&mut exports, field);
+        exports.push(*kind);
+        unsigned(&mut exports, *index as u64);
+    }
+
+    section(&mut wasm, 7, &exports);
+
+    let mut code = Vec::new();
+    unsigned(&mut code, module.functions.len() as u64);
+
+    for function in &module.functions {
+        let mut body = Vec::new();
+        let mut groups: Vec<(u32, Type)> = Vec::new();
+
+        for local in &function.locals {
+            match groups.last_mut() {
+                Some((count, t)) if t == local => *count += 1,
+                _ => groups.push((1, *local)),
+            }
+        }
+
+        unsigned(&mut body, groups.len() as u64);
+
+        for (count, t) in groups {
+            unsigned(&mut body, count as u64);
+            body.push(t.code());
+        }
+
+        for line in &function.body {
+            if let Line::Op(op) = line {
+                op.encode(&mut body);
+            }
+        }
+
+        Op::End.encode(&mut body);
+        unsigned(&mut code, body.len() as u64);
+        code.extend_from_slice(&body);
+    }
+
+    section(&mut wasm, 10, &code);
+
+    wasm
+}
+
+fn section(wasm: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    wasm.push(id);
+    unsigned(wasm, contents.len() as u64);
+    wasm.extend_from_slice(contents);
+}
@@ -0,0 +1,681 @@
+use crate::ir::{Instruction, IR};
+use crate::virtual_machine::Cell;
+
+// the generated function takes a pointer to a runtime with this layout and
+// returns one of the exit codes below, with the head and pc written back
+pub(crate) const CELLS: i32 = 0;
+pub(crate) const LENGTH: i32 = 8;
+pub(crate) const HEAD: i32 = 16;
+pub(crate) const PC: i32 = 24;
+pub(crate) const RESERVE: i32 = 32;
+pub(crate) const INPUT: i32 = 40;
+pub(crate) const OUTPUT: i32 = 48;
+
+pub(crate) const FINISHED: u64 = 0;
+pub(crate) const POINTER_UNDERFLOW: u64 = 1;
+pub(crate) const POINTER_OVERFLOW: u64 = 2;
+pub(crate) const IO: u64 = 3;
+
+pub(super) const RAX: u8 = 0;
+pub(super) const RCX: u8 = 1;
+pub(super) const RDX: u8 = 2;
+pub(super) const RBX: u8 = 3;
+pub(super) const RSI: u8 = 6;
+pub(super) const RDI: u8 = 7;
+pub(super) const R8: u8 = 8;
+pub(super) const R9: u8 = 9;
+pub(super) const R10: u8 = 10;
+pub(super) const R12: u8 = 12;
+pub(super) const R13: u8 = 13;
+pub(super) const R14: u8 = 14;
+pub(super) const R15: u8 = 15;
+
+// rbx holds the cells, r12 the head, r13 the tape length and r14 the runtime
+const TAPE: u8 = RBX;
+const HEAD_REGISTER: u8 = R12;
+const LENGTH_REGISTER: u8 = R13;
+const RUNTIME: u8 = R14;
+
+pub(super) const ADD: u8 = 0;
+pub(super) const SUB: u8 = 5;
+pub(super) const CMP: u8 = 7;
+
+#[derive(Clone, Copy)]
+pub(super) enum Condition {
+    Below = 0x2,
+    AboveOrEqual = 0x3,
+    Equal = 0x4,
+    NotEqual = 0x5,
+    Above = 0x7,
+    Less = 0xc,
+    LessOrEqual = 0xe,
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct Memory {
+    pub(super) base: u8,
+    pub(super) index: Option<(u8, u8)>,
+    pub(super) displacement: i32,
+}
+
+impl Memory {
+    pub(super) fn field(offset: i32) -> Self {
+        Self {
+            base: RUNTIME,
+            index: None,
+            displacement: offset,
+        }
+    }
+
+    fn cell<C: Cell>(index: u8) -> Self {
+        Self {
+            base: TAPE,
+            index: Some((index, (C::BITS / 8).trailing_zeros() as u8)),
+            displacement: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct Label(usize);
+
+enum Stub {
+    Exit(Label, u64, usize),
+    Io(Label, usize, isize),
+    Reserve(Label, Label, usize),
+}
+
+pub(super) struct Assembler {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    pub(super) fn new() -> Self {
+        Self {
+            code: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    pub(super) fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    pub(super) fn bind(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    pub(super) fn finish(mut self) -> Vec<u8> {
+        for (position, label) in &self.fixups {
+            let target = self.labels[label.0].unwrap() as i64;
+            let relative = (target - (*position as i64 + 4)) as i32;
+
+            self.code[*position..*position + 4].copy_from_slice(&relative.to_le_bytes());
+        }
+
+        self.code
+    }
+
+    pub(super) fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    pub(super) fn rex(&mut self, wide: bool, reg: u8, index: u8, base: u8) {
+        let rex = 0x40
+            | (wide as u8) << 3
+            | (reg >> 3 & 1) << 2
+            | (index >> 3 & 1) << 1
+            | (base >> 3 & 1);
+
+        if rex != 0x40 {
+            self.code.push(rex);
+        }
+    }
+
+    // an instruction with a memory operand, prefixed for the operand width
+    pub(super) fn memory(&mut self, width: u32, opcode: &[u8], reg: u8, memory: Memory) {
+        if width == 16 {
+            self.code.push(0x66);
+        }
+
+        let (index, scale) = memory.index.unwrap_or((4, 0));
+        self.rex(width == 64, reg, index, memory.base);
+        self.bytes(opcode);
+
+        let displacement = memory.displacement;
+        let mode = if displacement == 0 && memory.base & 7 != 5 {
+            0b00
+        } else if displacement as i8 as i32 == displacement {
+            0b01
+        } else {
+            0b10
+        };
+
+        if memory.index.is_some() || memory.base & 7 == 4 {
+            self.code.push(mode << 6 | (reg & 7) << 3 | 0b100);
+            self.code
+                .push(scale << 6 | (index & 7) << 3 | (memory.base & 7));
+        } else {
+            self.code
+                .push(mode << 6 | (reg & 7) << 3 | (memory.base & 7));
+        }
+
+        match mode {
+            0b01 => self.code.push(displacement as i8 as u8),
+            0b10 => self.bytes(&displacement.to_le_bytes()),
+            _ => (),
+        }
+    }
+
+    // a 64-bit instruction between two registers
+    pub(super) fn registers(&mut self, opcode: &[u8], reg: u8, rm: u8) {
+        self.rex(true, reg, 0, rm);
+        self.bytes(opcode);
+        self.code.push(0b11 << 6 | (reg & 7) << 3 | (rm & 7));
+    }
+
+    pub(super) fn push(&mut self, reg: u8) {
+        self.rex(false, 0, 0, reg);
+        self.code.push(0x50 | (reg & 7));
+    }
+
+    pub(super) fn pop(&mut self, reg: u8) {
+        self.rex(false, 0, 0, reg);
+        self.code.push(0x58 | (reg & 7));
+    }
+
+    pub(super) fn mov_immediate(&mut self, reg: u8, value: u64) {
+        if value <= u32::MAX as u64 {
+            self.rex(false, 0, 0, reg);
+            self.code.push(0xb8 | (reg & 7));
+            self.bytes(&(value as u32).to_le_bytes());
+        } else {
+            self.rex(true, 0, 0, reg);
+            self.code.push(0xb8 | (reg & 7));
+            self.bytes(&value.to_le_bytes());
+        }
+    }
+
+    pub(super) fn mov(&mut self, destination: u8, source: u8) {
+        self.registers(&[0x89], source, destination);
+    }
+
+    pub(super) fn load(&mut self, reg: u8, memory: Memory) {
+        self.memory(64, &[0x8b], reg, memory);
+    }
+
+    pub(super) fn store(&mut self, memory: Memory, reg: u8) {
+        self.memory(64, &[0x89], reg, memory);
+    }
+
+    pub(super) fn lea(&mut self, reg: u8, memory: Memory) {
+        self.memory(64, &[0x8d], reg, memory);
+    }
+
+    // add, sub or cmp with a register and an unsigned constant
+    pub(super) fn arithmetic(&mut self, operation: u8, reg: u8, value: usize) {
+        if value <= i8::MAX as usize {
+            self.registers(&[0x83], operation, reg);
+            self.code.push(value as u8);
+        } else if value <= i32::MAX as usize {
+            self.registers(&[0x81], operation, reg);
+            self.bytes(&(value as u32).to_le_bytes());
+        } else {
+            self.mov_immediate(RCX, value as u64);
+            self.registers(&[operation << 3 | 0x01], RCX, reg);
+        }
+    }
+
+    pub(super) fn call(&mut self, offset: i32) {
+        self.memory(32, &[0xff], 2, Memory::field(offset));
+    }
+
+    pub(super) fn test_al(&mut self) {
+        self.bytes(&[0x84, 0xc0]);
+    }
+
+    pub(super) fn jump(&mut self, condition: Option<Condition>, label: Label) {
+        match condition {
+            Some(condition) => self.bytes(&[0x0f, 0x80 | condition as u8]),
+            None => self.code.push(0xe9),
+        }
+
+        self.fixups.push((self.code.len(), label));
+        self.bytes(&[0; 4]);
+    }
+
+    pub(super) fn call_label(&mut self, label: Label) {
+        self.code.push(0xe8);
+        self.fixups.push((self.code.len(), label));
+        self.bytes(&[0; 4]);
+    }
+
+    pub(super) fn syscall(&mut self) {
+        self.bytes(&[0x0f, 0x05]);
+    }
+
+    pub(super) fn position(&self) -> usize {
+        self.code.len()
+    }
+
+    pub(super) fn ret(&mut self) {
+        self.code.push(0xc3);
+    }
+
+    // add, sub or cmp with a cell and a constant already reduced to the cell width
+    pub(super) fn cell_arithmetic(
+        &mut self,
+        width: u32,
+        operation: u8,
+        memory: Memory,
+        value: u64,
+    ) {
+        let signed = (value << (64 - width)) as i64 >> (64 - width);
+
+        if width == 8 {
+            self.memory(8, &[0x80], operation, memory);
+            self.code.push(value as u8);
+        } else if signed as i8 as i64 == signed {
+            self.memory(width, &[0x83], operation, memory);
+            self.code.push(signed as u8);
+        } else if width == 16 {
+            self.memory(16, &[0x81], operation, memory);
+            self.bytes(&(value as u16).to_le_bytes());
+        } else if signed as i32 as i64 == signed {
+            self.memory(width, &[0x81], operation, memory);
+            self.bytes(&(signed as u32).to_le_bytes());
+        } else {
+            self.mov_immediate(RCX, value);
+            self.memory(64, &[operation << 3 | 0x01], RCX, memory);
+        }
+    }
+
+    pub(super) fn cell_set(&mut self, width: u32, memory: Memory, value: u64) {
+        let signed = value as i64;
+
+        match width {
+            8 => {
+                self.memory(8, &[0xc6], 0, memory);
+                self.code.push(value as u8);
+            }
+            16 => {
+                self.memory(16, &[0xc7], 0, memory);
+                self.bytes(&(value as u16).to_le_bytes());
+            }
+            32 => {
+                self.memory(32, &[0xc7], 0, memory);
+                self.bytes(&(value as u32).to_le_bytes());
+            }
+            _ if signed as i32 as i64 == signed => {
+                self.memory(64, &[0xc7], 0, memory);
+                self.bytes(&(signed as u32).to_le_bytes());
+            }
+            _ => {
+                self.mov_immediate(RCX, value);
+                self.memory(64, &[0x89], RCX, memory);
+            }
+        }
+    }
+
+    // zero extends the cell into the whole register
+    pub(super) fn cell_load(&mut self, width: u32, reg: u8, memory: Memory) {
+        match width {
+            8 => self.memory(32, &[0x0f, 0xb6], reg, memory),
+            16 => self.memory(32, &[0x0f, 0xb7], reg, memory),
+            32 => self.memory(32, &[0x8b], reg, memory),
+            _ => self.memory(64, &[0x8b], reg, memory),
+        }
+    }
+
+    // add or sub a register into a cell, the opcode is the 8-bit form
+    pub(super) fn cell_update(&mut self, width: u32, opcode: u8, memory: Memory, reg: u8) {
+        match width {
+            8 => self.memory(8, &[opcode], reg, memory),
+            _ => self.memory(width, &[opcode | 1], reg, memory),
+        }
+    }
+}
+
+struct Compiler<C: Cell> {
+    asm: Assembler,
+    stubs: Vec<Stub>,
+    exit: Label,
+    cell: std::marker::PhantomData<C>,
+}
+
+impl<C: Cell> Compiler<C> {
+    fn width(&self) -> u32 {
+        C::BITS
+    }
+
+    fn head(&self) -> Memory {
+        Memory::cell::<C>(HEAD_REGISTER)
+    }
+
+    fn target(&self) -> Memory {
+        Memory::cell::<C>(RAX)
+    }
+
+    fn exit_stub(&mut self, code: u64, pc: usize) -> Label {
+        let label = self.asm.label();
+        self.stubs.push(Stub::Exit(label, code, pc));
+        label
+    }
+
+    fn check_left(&mut self, a: usize, pc: usize) {
+        let underflow = self.exit_stub(POINTER_UNDERFLOW, pc);
+
+        self.asm.arithmetic(CMP, HEAD_REGISTER, a);
+        self.asm.jump(Some(Condition::Below), underflow);
+    }
+
+    // leaves the index of the cell at the offset in rax, growing the tape if needed
+    fn offset(&mut self, offset: isize, pc: usize) {
+        if offset < 0 {
+            let a = offset.unsigned_abs();
+
+            self.check_left(a, pc);
+            self.asm.mov(RAX, HEAD_REGISTER);
+            self.asm.arithmetic(SUB, RAX, a);
+        } else {
+            let retry = self.asm.label();
+            let reserve = self.asm.label();
+
+            self.asm.bind(retry);
+
+            if offset <= i32::MAX as isize {
+                self.asm.lea(
+                    RAX,
+                    Memory {
+                        base: HEAD_REGISTER,
+                        index: None,
+                        displacement: offset as i32,
+                    },
+                );
+            } else {
+                self.asm.mov(RAX, HEAD_REGISTER);
+                self.asm.arithmetic(ADD, RAX, offset as usize);
+            }
+
+            self.asm.registers(&[0x39], LENGTH_REGISTER, RAX);
+            self.asm.jump(Some(Condition::AboveOrEqual), reserve);
+            self.stubs.push(Stub::Reserve(reserve, retry, pc));
+        }
+    }
+
+    fn call(&mut self, function: i32, index: u8, times: usize, pc: usize, offset: isize) {
+        let io = self.asm.label();
+
+        self.stubs.push(Stub::Io(io, pc, offset));
+
+        if index != RSI {
+            self.asm.mov(RSI, index);
+        }
+
+        self.asm.mov_immediate(RDX, times as u64);
+        self.asm.mov(RDI, RUNTIME);
+        self.asm.call(function);
+        self.asm.test_al();
+        self.asm.jump(Some(Condition::Equal), io);
+    }
+
+    fn input(&mut self, index: u8, times: usize, pc: usize, offset: isize) {
+        self.call(INPUT, index, times, pc, offset);
+    }
+
+    fn output(&mut self, memory: Memory, times: usize, pc: usize, offset: isize) {
+        self.asm.cell_load(self.width(), RSI, memory);
+        self.call(OUTPUT, RSI, times, pc, offset);
+    }
+
+    // adds the current cell times the factor to the cell at rax
+    fn multiply_add(&mut self, factor: isize) {
+        let width = self.width();
+
+        self.asm.cell_load(width, RDX, self.head());
+
+        match factor {
+            1 => self.asm.cell_update(width, 0x00, self.target(), RDX),
+            -1 => self.asm.cell_update(width, 0x28, self.target(), RDX),
+            _ if factor as i32 as isize == factor => {
+                self.asm.registers(&[0x69], RDX, RDX);
+                self.asm.bytes(&(factor as i32).to_le_bytes());
+                self.asm.cell_update(width, 0x00, self.target(), RDX);
+            }
+            _ => {
+                self.asm.mov_immediate(RCX, factor as u64);
+                self.asm.registers(&[0x0f, 0xaf], RDX, RCX);
+                self.asm.cell_update(width, 0x00, self.target(), RDX);
+            }
+        }
+    }
+
+    fn instruction(
+        &mut self,
+        pc: usize,
+        instruction: &Instruction,
+        brackets: &mut Vec<(Label, Label)>,
+    ) {
+        let width = self.width();
+        let wrap = |value: u64| C::from_u64(value).to_u64();
+
+        match instruction {
+            Instruction::NOP => (),
+
+            Instruction::Left(a) => {
+                self.check_left(*a, pc);
+                self.asm.arithmetic(SUB, HEAD_REGISTER, *a);
+            }
+
+            Instruction::Right(a) => {
+                self.offset(*a as isize, pc);
+                self.asm.mov(HEAD_REGISTER, RAX);
+            }
+
+            Instruction::Add(a) => {
+                self.asm
+                    .cell_arithmetic(width, ADD, self.head(), wrap(*a as u64))
+            }
+
+            Instruction::Sub(a) => {
+                self.asm
+                    .cell_arithmetic(width, SUB, self.head(), wrap(*a as u64))
+            }
+
+            Instruction::Input(times) => self.input(HEAD_REGISTER, *times, pc, 0),
+
+            Instruction::Output(times) => self.output(self.head(), *times, pc, 0),
+
+            Instruction::Open(_) => {
+                let (body, end) = (self.asm.label(), self.asm.label());
+
+                self.asm.cell_arithmetic(width, CMP, self.head(), 0);
+                self.asm.jump(Some(Condition::Equal), end);
+                self.asm.bind(body);
+                brackets.push((body, end));
+            }
+
+            Instruction::Close(_) => {
+                let (body, end) = brackets.pop().unwrap();
+
+                self.asm.cell_arithmetic(width, CMP, self.head(), 0);
+                self.asm.jump(Some(Condition::NotEqual), body);
+                self.asm.bind(end);
+            }
+
+            Instruction::Zero => self.asm.cell_set(width, self.head(), 0),
+
+            Instruction::FindZeroLeft(a) | Instruction::FindZeroRight(a) => {
+                let (scan, end) = (self.asm.label(), self.asm.label());
+
+                self.asm.bind(scan);
+                self.asm.cell_arithmetic(width, CMP, self.head(), 0);
+                self.asm.jump(Some(Condition::Equal), end);
+
+                if let Instruction::FindZeroLeft(_) = instruction {
+                    self.check_left(*a, pc);
+                    self.asm.arithmetic(SUB, HEAD_REGISTER, *a);
+                } else {
+                    self.offset(*a as isize, pc);
+                    self.asm.mov(HEAD_REGISTER, RAX);
+                }
+
+                self.asm.jump(None, scan);
+                self.asm.bind(end);
+            }
+
+            Instruction::ZeroAddLeft(a) | Instruction::ZeroAddRight(a) => {
+                let offset = match instruction {
+                    Instruction::ZeroAddLeft(_) => -(*a as isize),
+                    _ => *a as isize,
+                };
+
+                self.multiply_add_loop(&[(offset, 1)], pc);
+            }
+
+            Instruction::MulAdd(pairs) => self.multiply_add_loop(pairs, pc),
+
+            Instruction::AddAt(offset, a) => {
+                self.offset(*offset, pc);
+                self.asm
+                    .cell_arithmetic(width, ADD, self.target(), wrap(*a as u64));
+            }
+
+            Instruction::SubAt(offset, a) => {
+                self.offset(*offset, pc);
+                self.asm
+                    .cell_arithmetic(width, SUB, self.target(), wrap(*a as u64));
+            }
+
+            Instruction::InputAt(offset, times) => {
+                self.offset(*offset, pc);
+                self.input(RAX, *times, pc, *offset);
+            }
+
+            Instruction::OutputAt(offset, times) => {
+                self.offset(*offset, pc);
+                self.output(self.target(), *times, pc, *offset);
+            }
+
+            Instruction::ZeroAt(offset) => {
+                self.offset(*offset, pc);
+                self.asm.cell_set(width, self.target(), 0);
+            }
+
+            Instruction::Set(n) => self.asm.cell_set(width, self.head(), wrap(*n as u64)),
+
+            Instruction::SetAt(offset, n) => {
+                self.offset(*offset, pc);
+                self.asm.cell_set(width, self.target(), wrap(*n as u64));
+            }
+        }
+    }
+
+    fn multiply_add_loop(&mut self, pairs: &[(isize, isize)], pc: usize) {
+        let width = self.width();
+        let end = self.asm.label();
+
+        self.asm.cell_arithmetic(width, CMP, self.head(), 0);
+        self.asm.jump(Some(Condition::Equal), end);
+
+        for (offset, factor) in pairs {
+            self.offset(*offset, pc);
+            self.multiply_add(*factor);
+        }
+
+        self.asm.cell_set(width, self.head(), 0);
+        self.asm.bind(end);
+    }
+
+    fn stubs(&mut self) {
+        for stub in std::mem::take(&mut self.stubs) {
+            match stub {
+                Stub::Exit(label, code, pc) => {
+                    self.asm.bind(label);
+                    self.asm.mov_immediate(RCX, pc as u64);
+                    self.asm.mov_immediate(RAX, code);
+                    self.asm.jump(None, self.exit);
+                }
+                // io errors report the cell that was read or written as the head
+                Stub::Io(label, pc, offset) => {
+                    self.asm.bind(label);
+
+                    if offset < 0 {
+                        self.asm
+                            .arithmetic(SUB, HEAD_REGISTER, offset.unsigned_abs());
+                    } else if offset > 0 {
+                        self.asm.arithmetic(ADD, HEAD_REGISTER, offset as usize);
+                    }
+
+                    self.asm.mov_immediate(RCX, pc as u64);
+                    self.asm.mov_immediate(RAX, IO);
+                    self.asm.jump(None, self.exit);
+                }
+                Stub::Reserve(label, retry, pc) => {
+                    let overflow = self.exit_stub(POINTER_OVERFLOW, pc);
+
+                    self.asm.bind(label);
+                    self.asm.mov(RSI, RAX);
+                    self.asm.mov(RDI, RUNTIME);
+                    self.asm.call(RESERVE);
+                    self.asm.test_al();
+                    self.asm.jump(Some(Condition::Equal), overflow);
+                    self.asm.load(TAPE, Memory::field(CELLS));
+                    self.asm.load(LENGTH_REGISTER, Memory::field(LENGTH));
+                    self.asm.jump(None, retry);
+                }
+            }
+        }
+
+        if !self.stubs.is_empty() {
+            self.stubs();
+        }
+    }
+}
+
+// compiles the IR into a function that runs it from the head stored in the runtime
+pub(crate) fn compile<C: Cell>(ir: &IR) -> Vec<u8> {
+    let mut asm = Assembler::new();
+    let exit = asm.label();
+    let mut compiler = Compiler::<C> {
+        asm,
+        stubs: Vec::new(),
+        exit,
+        cell: std::marker::PhantomData,
+    };
+    let mut brackets = Vec::new();
+
+    // five pushes keep the stack aligned for the calls into the runtime
+    for reg in &[RBX, R12, R13, R14, R15] {
+        compiler.asm.push(*reg);
+    }
+
+    compiler.asm.mov(RUNTIME, RDI);
+    compiler.asm.load(TAPE, Memory::field(CELLS));
+    compiler.asm.load(LENGTH_REGISTER, Memory::field(LENGTH));
+    compiler.asm.load(HEAD_REGISTER, Memory::field(HEAD));
+
+    for (pc, instruction) in ir.vec().iter().enumerate() {
+        compiler.instruction(pc, instruction, &mut brackets);
+    }
+
+    compiler.asm.mov_immediate(RCX, ir.len() as u64);
+    compiler.asm.mov_immediate(RAX, FINISHED);
+    compiler.asm.jump(None, exit);
+    compiler.stubs();
+
+    compiler.asm.bind(exit);
+    compiler.asm.store(Memory::field(HEAD), HEAD_REGISTER);
+    compiler.asm.store(Memory::field(PC), RCX);
+
+    for reg in &[R15, R14, R13, R12, RBX] {
+        compiler.asm.pop(*reg);
+    }
+
+    compiler.asm.ret();
+    compiler.asm.finish()
+}
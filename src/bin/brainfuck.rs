@@ -1,30 +1,193 @@
+use brainfuck::backend::{emit_c, emit_elf, emit_llvm, emit_rust, emit_wasm, emit_wat, Target};
+use brainfuck::ir::{BytecodeError, IR};
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+use brainfuck::jit::JIT;
+use brainfuck::optimizer::{OptLevel, Pipeline};
 use brainfuck::parser::ParseResult;
 use brainfuck::parser::Parser;
-use brainfuck::virtual_machine::VM;
-use clap::{App, Arg};
+use brainfuck::tracer::{TraceFormat, Tracer};
+use brainfuck::virtual_machine::{
+    Cell, EofBehavior, RuntimeError, Snapshot, SnapshotError, Status, VM,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use colored::*;
 use line_col::LineColLookup;
+use std::ffi::OsStr;
 use std::fs;
-use std::io;
+use std::io::{self, Write};
+use std::path::Path;
 use std::process;
 
+const PROFILE_LINES: usize = 20;
+
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+const ENGINES: &[&str] = &["vm", "jit"];
+#[cfg(not(all(feature = "jit", target_arch = "x86_64", target_os = "linux")))]
+const ENGINES: &[&str] = &["vm"];
+
+// options that need the virtual machine to stop or look inside it
+const VM_OPTIONS: &[&str] = &[
+    "load-state",
+    "save-state",
+    "profile",
+    "profile-folded",
+    "trace",
+    "max-steps",
+];
+
+// what build can write and the extension of each, executables have none
+const FORMATS: &[(&str, &str)] = &[
+    ("bytecode", "bfc"),
+    ("ir", "bfir"),
+    ("c", "c"),
+    ("llvm", "ll"),
+    ("rust", "rs"),
+    ("wat", "wat"),
+    ("wasm", "wasm"),
+    ("elf", ""),
+];
+
 fn main() {
     let matches = App::new("brainfuck")
         .author(clap::crate_authors!())
         .version(clap::crate_version!())
         .about("A brainfuck interpreter written in Rust")
-        .arg(
-            Arg::with_name("INPUT")
-                .help("Program to interpret")
-                .index(1),
+        .args(&run_args())
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a brainfuck program or a compiled .bfc file")
+                .args(&run_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Compile a brainfuck program ahead of time")
+                .args(&program_args())
+                .args(&target_args())
+                .arg(
+                    Arg::with_name("emit")
+                        .long("emit")
+                        .value_name("FORMAT")
+                        .help(
+                            "What to write, bytecode for `run`, the IR as text, C, LLVM IR, \
+                             Rust, WebAssembly as text or binary or a Linux x86-64 \
+                             executable, defaults to the format for the extension of the \
+                             output",
+                        )
+                        .possible_values(&FORMATS.iter().map(|f| f.0).collect::<Vec<_>>()),
+                )
+                .arg(Arg::with_name("output").short("o").value_name("FILE").help(
+                    "Where to write, defaults to the input with the extension for the format",
+                )),
         )
         .get_matches();
 
+    match matches.subcommand() {
+        ("build", Some(matches)) => build(matches),
+        ("run", Some(matches)) => execute(matches),
+        _ => execute(&matches),
+    }
+
+    process::exit(exitcode::OK);
+}
+
+fn program_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("INPUT")
+            .help("Program to interpret")
+            .index(1),
+        Arg::with_name("opt-level")
+            .short("O")
+            .value_name("LEVEL")
+            .help("Optimization level, 0 keeps one instruction per source character")
+            .possible_values(&["0", "1", "2"])
+            .default_value("2"),
+    ]
+}
+
+fn target_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("cell-width")
+            .long("cell-width")
+            .value_name("BITS")
+            .help("Width of each tape cell")
+            .possible_values(&["8", "16", "32", "64"])
+            .default_value("8"),
+        Arg::with_name("eof")
+            .long("eof")
+            .value_name("BEHAVIOR")
+            .help("What the input instruction stores once input is exhausted")
+            .possible_values(&["error", "unchanged", "zero", "minus-one"])
+            .default_value("error"),
+    ]
+}
+
+fn run_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = program_args();
+
+    args.extend(target_args());
+    args.extend(vec![
+        Arg::with_name("engine")
+            .long("engine")
+            .value_name("ENGINE")
+            .help("Interpret the IR, or compile it to native code first with jit")
+            .possible_values(ENGINES)
+            .default_value("vm"),
+        Arg::with_name("load-state")
+            .long("load-state")
+            .value_name("FILE")
+            .help("Resume execution from a saved state"),
+        Arg::with_name("save-state")
+            .long("save-state")
+            .value_name("FILE")
+            .help("Save the state when execution stops"),
+        Arg::with_name("profile")
+            .long("profile")
+            .help("Print a report of the most executed instructions and loops"),
+        Arg::with_name("profile-folded")
+            .long("profile-folded")
+            .value_name("FILE")
+            .help("Write the profile as folded stacks for flamegraph tools"),
+        Arg::with_name("trace")
+            .long("trace")
+            .value_name("FILE")
+            .help("Write a record of every executed instruction"),
+        Arg::with_name("trace-format")
+            .long("trace-format")
+            .value_name("FORMAT")
+            .help("Format of the trace records")
+            .possible_values(&["text", "json"])
+            .default_value("text"),
+        Arg::with_name("max-steps")
+            .long("max-steps")
+            .value_name("STEPS")
+            .help("Stop after executing this many instructions")
+            .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string())),
+    ]);
+
+    args
+}
+
+// compiled programs carry no source, so their text is empty
+fn load(matches: &ArgMatches) -> (IR, String) {
     let file_name = matches.value_of("INPUT").exit_no_file();
+
+    if Path::new(file_name).extension() == Some(OsStr::new("bfc")) {
+        let mut file = fs::File::open(file_name).exit_bad_file(file_name);
+        let ir = IR::read_from(&mut io::BufReader::new(&mut file)).exit_bytecode(file_name);
+
+        return (ir, String::new());
+    }
+
     let text = fs::read_to_string(file_name).exit_bad_file(file_name);
     let lookup = LineColLookup::new(&text);
 
-    let mut parser = Parser::default();
+    let level = match matches.value_of("opt-level") {
+        Some("0") => OptLevel::O0,
+        Some("1") => OptLevel::O1,
+        _ => OptLevel::O2,
+    };
+
+    let mut parser = Parser::default().with_pipeline(Pipeline::new(level));
 
     parser.parse(&text).exit_parser(file_name, &text, &lookup);
 
@@ -43,11 +206,202 @@ fn main() {
         );
     }
 
-    // println!("{}", parser.ir());
+    (parser.into_ir(), text)
+}
 
-    VM::new(parser.ir()).run(&mut io::stdout(), &mut io::stdin());
+fn build(matches: &ArgMatches) {
+    let (ir, _) = load(matches);
+    let output = matches.value_of("output").map(Path::new);
 
-    process::exit(exitcode::OK);
+    // without --emit the format follows from the extension of the output, an
+    // output without one is an executable
+    let emit = match (matches.value_of("emit"), output) {
+        (Some(emit), _) => emit,
+        (None, Some(output)) => {
+            let extension = output.extension().map_or(Some(""), OsStr::to_str);
+
+            FORMATS
+                .iter()
+                .find(|(_, e)| Some(*e) == extension)
+                .map_or("bytecode", |(format, _)| format)
+        }
+        (None, None) => "bytecode",
+    };
+    let file_name = match output {
+        Some(output) => output.to_path_buf(),
+        None => {
+            let (_, extension) = FORMATS.iter().find(|(format, _)| *format == emit).unwrap();
+
+            Path::new(matches.value_of("INPUT").unwrap()).with_extension(extension)
+        }
+    };
+    let file_name = file_name.to_string_lossy();
+
+    let mut file = io::BufWriter::new(fs::File::create(&*file_name).exit_bad_file(&file_name));
+
+    match emit {
+        "ir" => writeln!(file, "{}", ir)
+            .and_then(|_| file.flush())
+            .exit_bad_file(&file_name),
+        "c" | "llvm" | "rust" | "wat" | "wasm" | "elf" => {
+            let target = Target::default().with_eof_behavior(eof_behavior(matches));
+            let output = match matches.value_of("cell-width") {
+                Some("16") => compile::<u16>(&ir, emit, &target),
+                Some("32") => compile::<u32>(&ir, emit, &target),
+                Some("64") => compile::<u64>(&ir, emit, &target),
+                _ => compile::<u8>(&ir, emit, &target),
+            };
+
+            file.write_all(&output)
+                .and_then(|_| file.flush())
+                .exit_bad_file(&file_name);
+
+            #[cfg(unix)]
+            if emit == "elf" {
+                use std::os::unix::fs::PermissionsExt;
+
+                fs::set_permissions(&*file_name, fs::Permissions::from_mode(0o755))
+                    .exit_bad_file(&file_name);
+            }
+        }
+        _ => ir
+            .write_to(&mut file)
+            .and_then(|_| file.flush().map_err(BytecodeError::from))
+            .exit_bytecode(&file_name),
+    }
+}
+
+fn compile<C: Cell>(ir: &IR, emit: &str, target: &Target) -> Vec<u8> {
+    match emit {
+        "c" => emit_c::<C>(ir, target).into_bytes(),
+        "llvm" => emit_llvm::<C>(ir, target).into_bytes(),
+        "rust" => emit_rust::<C>(ir, target).into_bytes(),
+        "wat" => emit_wat::<C>(ir, target).into_bytes(),
+        "wasm" => emit_wasm::<C>(ir, target),
+        _ => emit_elf::<C>(ir, target),
+    }
+}
+
+fn execute(matches: &ArgMatches) {
+    let file_name = matches.value_of("INPUT").exit_no_file();
+    let (ir, text) = load(matches);
+
+    match matches.value_of("cell-width") {
+        Some("16") => run::<u16>(&ir, file_name, &text, matches),
+        Some("32") => run::<u32>(&ir, file_name, &text, matches),
+        Some("64") => run::<u64>(&ir, file_name, &text, matches),
+        _ => run::<u8>(&ir, file_name, &text, matches),
+    }
+}
+
+fn eof_behavior(matches: &ArgMatches) -> EofBehavior {
+    match matches.value_of("eof") {
+        Some("unchanged") => EofBehavior::Unchanged,
+        Some("zero") => EofBehavior::Zero,
+        Some("minus-one") => EofBehavior::MinusOne,
+        _ => EofBehavior::Error,
+    }
+}
+
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+fn run_jit<C: Cell>(ir: &IR, file_name: &str, text: &str, matches: &ArgMatches) {
+    JIT::<C>::new_generic(ir)
+        .with_eof_behavior(eof_behavior(matches))
+        .run(&mut io::stdout(), &mut io::stdin())
+        .exit_runtime(file_name, text, ir);
+}
+
+fn run<C: Cell>(ir: &IR, file_name: &str, text: &str, matches: &ArgMatches) {
+    if matches.value_of("engine") == Some("jit") {
+        if let Some(option) = VM_OPTIONS.iter().find(|o| matches.is_present(o)) {
+            eprintln!(
+                "{} {}",
+                "fatal error:".red().bold(),
+                format!("--{} needs --engine vm", option).bold()
+            );
+            process::exit(exitcode::USAGE);
+        }
+
+        #[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+        return run_jit::<C>(ir, file_name, text, matches);
+    }
+
+    let mut vm = VM::<C>::new_generic(ir).with_eof_behavior(eof_behavior(matches));
+
+    if let Some(file_name) = matches.value_of("trace") {
+        let file = fs::File::create(file_name).exit_bad_file(file_name);
+        let format = match matches.value_of("trace-format") {
+            Some("json") => TraceFormat::JsonLines,
+            _ => TraceFormat::Text,
+        };
+
+        vm = vm.with_tracer(Tracer::new(io::BufWriter::new(file), format));
+    }
+
+    if matches.is_present("profile") || matches.is_present("profile-folded") {
+        vm = vm.with_profiler();
+    }
+
+    if let Some(file_name) = matches.value_of("load-state") {
+        let mut file = fs::File::open(file_name).exit_bad_file(file_name);
+        let snapshot =
+            Snapshot::read_from(&mut io::BufReader::new(&mut file)).exit_snapshot(file_name);
+
+        vm.restore(&snapshot).exit_snapshot(file_name);
+    }
+
+    let result = match matches.value_of("max-steps") {
+        Some(steps) => {
+            vm.run_with_fuel(steps.parse().unwrap(), &mut io::stdout(), &mut io::stdin())
+        }
+        None => vm
+            .run(&mut io::stdout(), &mut io::stdin())
+            .map(|_| Status::Finished),
+    };
+
+    if let Some(file_name) = matches.value_of("trace") {
+        vm.flush_trace().exit_bad_file(file_name);
+    }
+
+    let status = result.exit_runtime(file_name, text, ir);
+
+    if let Some(file_name) = matches.value_of("save-state") {
+        let mut file = fs::File::create(file_name).exit_bad_file(file_name);
+
+        vm.snapshot()
+            .write_to(&mut io::BufWriter::new(&mut file))
+            .exit_snapshot(file_name);
+    }
+
+    if let Some(profile) = vm.profile() {
+        if matches.is_present("profile") {
+            eprint!("{}", profile.report(ir, text, PROFILE_LINES));
+        }
+
+        if let Some(file_name) = matches.value_of("profile-folded") {
+            fs::write(file_name, profile.folded(ir, text)).exit_bad_file(file_name);
+        }
+    }
+
+    let (message, code) = match status {
+        Status::Finished => return,
+        Status::OutOfFuel => (
+            format!(
+                "step limit of {} exceeded",
+                matches.value_of("max-steps").unwrap()
+            ),
+            exitcode::SOFTWARE,
+        ),
+        _ => (String::from("input or output would block"), exitcode::IOERR),
+    };
+
+    eprintln!("{} {}", "runtime error:".red().bold(), message.bold());
+
+    if !vm.done() {
+        print_source_line(file_name, text, ir, vm.pc());
+    }
+
+    process::exit(code);
 }
 
 pub trait OptionError<T> {
@@ -119,6 +473,98 @@ impl ExitParserError for ParseResult {
     }
 }
 
+trait ExitSnapshotError<T> {
+    fn exit_snapshot(self, file_name: &str) -> T;
+}
+
+impl<T> ExitSnapshotError<T> for Result<T, SnapshotError> {
+    fn exit_snapshot(self, file_name: &str) -> T {
+        match self {
+            Ok(val) => val,
+            Err(error) => {
+                eprintln!(
+                    "{} {}: {}",
+                    "fatal error:".red().bold(),
+                    error.to_string().bold(),
+                    file_name.bold()
+                );
+
+                process::exit(match error {
+                    SnapshotError::Io(_) => exitcode::IOERR,
+                    _ => exitcode::DATAERR,
+                });
+            }
+        }
+    }
+}
+
+trait ExitBytecodeError<T> {
+    fn exit_bytecode(self, file_name: &str) -> T;
+}
+
+impl<T> ExitBytecodeError<T> for Result<T, BytecodeError> {
+    fn exit_bytecode(self, file_name: &str) -> T {
+        match self {
+            Ok(val) => val,
+            Err(error) => {
+                eprintln!(
+                    "{} {}: {}",
+                    "fatal error:".red().bold(),
+                    error.to_string().bold(),
+                    file_name.bold()
+                );
+
+                process::exit(match error {
+                    BytecodeError::Io(_) => exitcode::IOERR,
+                    _ => exitcode::DATAERR,
+                });
+            }
+        }
+    }
+}
+
+trait ExitRuntimeError<T> {
+    fn exit_runtime(self, file_name: &str, text: &str, ir: &IR) -> T;
+}
+
+impl<T> ExitRuntimeError<T> for Result<T, RuntimeError> {
+    fn exit_runtime(self, file_name: &str, text: &str, ir: &IR) -> T {
+        match self {
+            Ok(val) => val,
+            Err(error) => {
+                eprintln!(
+                    "{} {}",
+                    "runtime error:".red().bold(),
+                    error.to_string().bold(),
+                );
+
+                print_source_line(file_name, text, ir, error.pc());
+
+                process::exit(match error {
+                    RuntimeError::Io(..) => exitcode::IOERR,
+                    _ => exitcode::SOFTWARE,
+                });
+            }
+        }
+    }
+}
+
+fn print_source_line(file_name: &str, text: &str, ir: &IR, pc: usize) {
+    if text.is_empty() {
+        return;
+    }
+
+    let span = ir.span(pc);
+
+    print_warning_line(
+        file_name,
+        text,
+        span.beginning,
+        span.end,
+        &LineColLookup::new(text),
+    );
+}
+
 fn print_warning_line(
     file_name: &str,
     text: &str,
@@ -127,8 +573,14 @@ fn print_warning_line(
     lookup: &LineColLookup,
 ) {
     let (line_b, column_b) = lookup.get(beginning);
-    let (_line_e, column_e) = lookup.get(end);
+    let (line_e, column_e) = lookup.get(end);
     let line_str = text.lines().nth(line_b - 1).unwrap();
+    // spans over several lines are underlined to the end of the first one
+    let column_e = if line_e == line_b {
+        column_e
+    } else {
+        line_str.chars().count().max(column_b)
+    };
     let line_b_spaces = " ".repeat(line_b.to_string().len());
 
     eprintln!(
@@ -0,0 +1,281 @@
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use thiserror::Error;
+
+use super::{Instruction, Span, VerifyError, IR};
+
+const MAGIC: &[u8; 4] = b"BFBC";
+const VERSION: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum BytecodeError {
+    #[error("i/o error: {}", .0)]
+    Io(#[from] io::Error),
+    #[error("not a brainfuck bytecode file")]
+    BadMagic,
+    #[error("unsupported bytecode version {}", .0)]
+    UnsupportedVersion(u8),
+    #[error("bytecode checksum does not match")]
+    Checksum,
+    #[error("unknown opcode {} at byte {}", .1, .0)]
+    UnknownOpcode(usize, u8),
+    #[error("bytecode ends in the middle of an instruction")]
+    Truncated,
+    #[error("bytecode value {} does not fit in memory", .0)]
+    Overflow(u64),
+    #[error("bytecode has unbalanced brackets")]
+    MismatchedBracket,
+    #[error("{}", .0)]
+    Invalid(#[from] VerifyError),
+}
+
+impl IR {
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> Result<(), BytecodeError> {
+        let mut payload = Vec::with_capacity(self.len() * 2);
+
+        for instruction in self.vec() {
+            encode(instruction, &mut payload);
+        }
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+
+        for value in &[
+            self.len() as u64,
+            payload.len() as u64,
+            super::fnv1a(payload.iter().copied()),
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        writer.write_all(&payload)?;
+
+        Ok(())
+    }
+
+    pub fn read_from<R: io::Read>(reader: &mut R) -> Result<Self, BytecodeError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err(BytecodeError::BadMagic);
+        }
+
+        let mut version = [0; 1];
+        reader.read_exact(&mut version)?;
+
+        if version[0] != VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version[0]));
+        }
+
+        let count = read_usize(reader)?;
+        let length = read_usize(reader)?;
+        let checksum = read_u64(reader)?;
+
+        let mut payload = Vec::new();
+        reader
+            .by_ref()
+            .take(length as u64)
+            .read_to_end(&mut payload)?;
+
+        if payload.len() != length {
+            return Err(BytecodeError::Truncated);
+        }
+
+        if super::fnv1a(payload.iter().copied()) != checksum {
+            return Err(BytecodeError::Checksum);
+        }
+
+        let mut decoder = Decoder {
+            bytes: &payload,
+            position: 0,
+        };
+        let mut ir = IR::default();
+        let mut depth = 0usize;
+
+        while decoder.position < payload.len() {
+            let instruction = decoder.instruction()?;
+
+            match instruction {
+                Instruction::Open(_) => depth += 1,
+                Instruction::Close(_) => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or(BytecodeError::MismatchedBracket)?
+                }
+                _ => (),
+            }
+
+            ir.push(instruction, Span::default());
+        }
+
+        if depth != 0 {
+            return Err(BytecodeError::MismatchedBracket);
+        }
+
+        if ir.len() != count {
+            return Err(BytecodeError::Truncated);
+        }
+
+        ir.link();
+        // a valid checksum only means the file is intact, not that it runs
+        ir.verify()?;
+
+        Ok(ir)
+    }
+}
+
+// one opcode byte per instruction, followed by its arguments as LEB128
+// varints, zigzag encoded where they can be negative
+fn encode(instruction: &Instruction, bytes: &mut Vec<u8>) {
+    let (opcode, unsigned, signed): (u8, &[usize], &[isize]) = match instruction {
+        Instruction::NOP => (0, &[], &[]),
+        Instruction::Left(a) => (1, &[*a], &[]),
+        Instruction::Right(a) => (2, &[*a], &[]),
+        Instruction::Add(a) => (3, &[*a], &[]),
+        Instruction::Sub(a) => (4, &[*a], &[]),
+        Instruction::Input(a) => (5, &[*a], &[]),
+        Instruction::Output(a) => (6, &[*a], &[]),
+        Instruction::Open(_) => (7, &[], &[]),
+        Instruction::Close(_) => (8, &[], &[]),
+        Instruction::Zero => (9, &[], &[]),
+        Instruction::FindZeroLeft(a) => (10, &[*a], &[]),
+        Instruction::FindZeroRight(a) => (11, &[*a], &[]),
+        Instruction::ZeroAddLeft(a) => (12, &[*a], &[]),
+        Instruction::ZeroAddRight(a) => (13, &[*a], &[]),
+        Instruction::MulAdd(pairs) => {
+            bytes.push(14);
+            write_unsigned(bytes, pairs.len() as u64);
+
+            for (offset, factor) in pairs {
+                write_signed(bytes, *offset as i64);
+                write_signed(bytes, *factor as i64);
+            }
+
+            return;
+        }
+        Instruction::AddAt(o, a) => (15, &[*a], &[*o]),
+        Instruction::SubAt(o, a) => (16, &[*a], &[*o]),
+        Instruction::InputAt(o, a) => (17, &[*a], &[*o]),
+        Instruction::OutputAt(o, a) => (18, &[*a], &[*o]),
+        Instruction::ZeroAt(o) => (19, &[], &[*o]),
+        Instruction::Set(n) => (20, &[], &[*n]),
+        Instruction::SetAt(o, n) => (21, &[], &[*o, *n]),
+    };
+
+    bytes.push(opcode);
+
+    for value in signed {
+        write_signed(bytes, *value as i64);
+    }
+
+    for value in unsigned {
+        write_unsigned(bytes, *value as u64);
+    }
+}
+
+fn write_unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    bytes.push(value as u8);
+}
+
+fn write_signed(bytes: &mut Vec<u8>, value: i64) {
+    write_unsigned(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn instruction(&mut self) -> Result<Instruction, BytecodeError> {
+        let position = self.position;
+        let opcode = self.byte()?;
+
+        Ok(match opcode {
+            0 => Instruction::NOP,
+            1 => Instruction::Left(self.unsigned()?),
+            2 => Instruction::Right(self.unsigned()?),
+            3 => Instruction::Add(self.unsigned()?),
+            4 => Instruction::Sub(self.unsigned()?),
+            5 => Instruction::Input(self.unsigned()?),
+            6 => Instruction::Output(self.unsigned()?),
+            7 => Instruction::Open(0),
+            8 => Instruction::Close(0),
+            9 => Instruction::Zero,
+            10 => Instruction::FindZeroLeft(self.unsigned()?),
+            11 => Instruction::FindZeroRight(self.unsigned()?),
+            12 => Instruction::ZeroAddLeft(self.unsigned()?),
+            13 => Instruction::ZeroAddRight(self.unsigned()?),
+            14 => {
+                let length = self.unsigned()?;
+                let mut pairs = Vec::with_capacity(length.min(self.bytes.len()));
+
+                for _ in 0..length {
+                    pairs.push((self.signed()?, self.signed()?));
+                }
+
+                Instruction::MulAdd(pairs)
+            }
+            15 => Instruction::AddAt(self.signed()?, self.unsigned()?),
+            16 => Instruction::SubAt(self.signed()?, self.unsigned()?),
+            17 => Instruction::InputAt(self.signed()?, self.unsigned()?),
+            18 => Instruction::OutputAt(self.signed()?, self.unsigned()?),
+            19 => Instruction::ZeroAt(self.signed()?),
+            20 => Instruction::Set(self.signed()?),
+            21 => Instruction::SetAt(self.signed()?, self.signed()?),
+            _ => return Err(BytecodeError::UnknownOpcode(position, opcode)),
+        })
+    }
+
+    fn byte(&mut self) -> Result<u8, BytecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or(BytecodeError::Truncated)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, BytecodeError> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(BytecodeError::Overflow(value))
+    }
+
+    fn unsigned(&mut self) -> Result<usize, BytecodeError> {
+        let value = self.varint()?;
+        usize::try_from(value).map_err(|_| BytecodeError::Overflow(value))
+    }
+
+    fn signed(&mut self) -> Result<isize, BytecodeError> {
+        let value = self.varint()?;
+        let decoded = (value >> 1) as i64 ^ -((value & 1) as i64);
+        isize::try_from(decoded).map_err(|_| BytecodeError::Overflow(value))
+    }
+}
+
+fn read_u64<R: io::Read>(reader: &mut R) -> Result<u64, BytecodeError> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+fn read_usize<R: io::Read>(reader: &mut R) -> Result<usize, BytecodeError> {
+    let value = read_u64(reader)?;
+    usize::try_from(value).map_err(|_| BytecodeError::Overflow(value))
+}
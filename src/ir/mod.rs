@@ -0,0 +1,583 @@
+mod bytecode;
+mod text;
+mod verify;
+
+pub use bytecode::BytecodeError;
+pub use text::TextError;
+pub use verify::VerifyError;
+
+use std::collections::BTreeMap;
+use std::convert;
+use std::fmt;
+use std::ops;
+
+use itertools::Itertools;
+
+#[derive(Clone)]
+pub enum Instruction {
+    NOP,
+    Left(usize),
+    Right(usize),
+    Add(usize),
+    Sub(usize),
+    Input(usize),
+    Output(usize),
+    Open(usize),
+    Close(usize),
+    Zero,
+    FindZeroLeft(usize),
+    FindZeroRight(usize),
+    ZeroAddLeft(usize),
+    ZeroAddRight(usize),
+    MulAdd(Vec<(isize, isize)>),
+    AddAt(isize, usize),
+    SubAt(isize, usize),
+    InputAt(isize, usize),
+    OutputAt(isize, usize),
+    ZeroAt(isize),
+    Set(isize),
+    SetAt(isize, isize),
+}
+
+impl Instruction {
+    pub fn modify_argument<F>(&mut self, f: F)
+    where
+        F: FnOnce(usize) -> usize,
+    {
+        match self {
+            Self::NOP
+            | Self::Zero
+            | Self::MulAdd(_)
+            | Self::ZeroAt(_)
+            | Self::Set(_)
+            | Self::SetAt(..) => (),
+            Self::Left(a)
+            | Self::Right(a)
+            | Self::Add(a)
+            | Self::Sub(a)
+            | Self::Input(a)
+            | Self::Output(a)
+            | Self::Open(a)
+            | Self::Close(a)
+            | Self::FindZeroLeft(a)
+            | Self::FindZeroRight(a)
+            | Self::ZeroAddLeft(a)
+            | Self::ZeroAddRight(a)
+            | Self::AddAt(_, a)
+            | Self::SubAt(_, a)
+            | Self::InputAt(_, a)
+            | Self::OutputAt(_, a) => *a = f(*a),
+        }
+    }
+
+    fn is_degenerate(&self) -> bool {
+        match self {
+            Self::Left(a)
+            | Self::Right(a)
+            | Self::Add(a)
+            | Self::Sub(a)
+            | Self::Input(a)
+            | Self::Output(a)
+            | Self::AddAt(_, a)
+            | Self::SubAt(_, a)
+            | Self::InputAt(_, a)
+            | Self::OutputAt(_, a)
+            | Self::FindZeroLeft(a)
+            | Self::FindZeroRight(a)
+            | Self::ZeroAddLeft(a)
+            | Self::ZeroAddRight(a) => *a == 0,
+            // the counter cell is cleared last, so it can not be a target
+            Self::MulAdd(pairs) => {
+                pairs.is_empty()
+                    || pairs
+                        .iter()
+                        .any(|(offset, factor)| *offset == 0 || *factor == 0)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn combine(&self, other: &Self) -> Option<Self> {
+        let new_instruction = match (self, other) {
+            (Self::Left(a), Self::Left(b)) => Self::Left(a + b),
+            (Self::Right(a), Self::Right(b)) => Self::Right(a + b),
+            (Self::Left(l), Self::Right(r)) | (Self::Right(r), Self::Left(l)) => {
+                if r > l {
+                    Self::Right(r - l)
+                } else {
+                    Self::Left(l - r)
+                }
+            }
+
+            (Self::Add(a), Self::Add(b)) => Self::Add(a + b),
+            (Self::Sub(a), Self::Sub(b)) => Self::Sub(a + b),
+            (Self::Add(a), Self::Sub(s)) | (Self::Sub(s), Self::Add(a)) => {
+                if a > s {
+                    Self::Add(a - s)
+                } else {
+                    Self::Sub(s - a)
+                }
+            }
+
+            (Self::Input(a), Self::Input(b)) => Self::Input(a + b),
+            (Self::Output(a), Self::Output(b)) => Self::Output(a + b),
+            (Self::Zero, Self::Zero) => Self::Zero,
+
+            (Self::Zero, Self::Add(a)) => Self::Set(*a as isize),
+            (Self::Zero, Self::Sub(s)) => Self::Set((*s as isize).wrapping_neg()),
+            (Self::Set(n), Self::Add(a)) => Self::Set(n.wrapping_add(*a as isize)),
+            (Self::Set(n), Self::Sub(s)) => Self::Set(n.wrapping_sub(*s as isize)),
+            (Self::Zero, Self::Set(n)) | (Self::Set(_), Self::Set(n)) => Self::Set(*n),
+            (Self::Set(_), Self::Zero) => Self::Zero,
+            _ => return None,
+        };
+
+        Some(if new_instruction.is_degenerate() {
+            Self::NOP
+        } else if let Self::Set(0) = new_instruction {
+            Self::Zero
+        } else {
+            new_instruction
+        })
+    }
+
+    pub fn argument(&self) -> usize {
+        match self {
+            Self::NOP
+            | Self::Zero
+            | Self::MulAdd(_)
+            | Self::ZeroAt(_)
+            | Self::Set(_)
+            | Self::SetAt(..) => 0,
+            Self::Left(a)
+            | Self::Right(a)
+            | Self::Add(a)
+            | Self::Sub(a)
+            | Self::Input(a)
+            | Self::Output(a)
+            | Self::Open(a)
+            | Self::Close(a)
+            | Self::FindZeroLeft(a)
+            | Self::FindZeroRight(a)
+            | Self::ZeroAddLeft(a)
+            | Self::ZeroAddRight(a)
+            | Self::AddAt(_, a)
+            | Self::SubAt(_, a)
+            | Self::InputAt(_, a)
+            | Self::OutputAt(_, a) => *a,
+        }
+    }
+
+    pub fn is_nop(&self) -> bool {
+        matches!(self, Self::NOP)
+    }
+
+    pub fn is_left(&self) -> bool {
+        matches!(self, Self::Left(_))
+    }
+
+    pub fn is_right(&self) -> bool {
+        matches!(self, Self::Right(_))
+    }
+
+    pub fn is_add(&self) -> bool {
+        matches!(self, Self::Add(_))
+    }
+
+    pub fn is_sub(&self) -> bool {
+        matches!(self, Self::Sub(_))
+    }
+
+    pub fn is_input(&self) -> bool {
+        matches!(self, Self::Input(_) | Self::InputAt(..))
+    }
+
+    pub fn is_output(&self) -> bool {
+        matches!(self, Self::Output(_) | Self::OutputAt(..))
+    }
+
+    pub fn offset(&self) -> isize {
+        match self {
+            Self::AddAt(offset, _)
+            | Self::SubAt(offset, _)
+            | Self::InputAt(offset, _)
+            | Self::OutputAt(offset, _)
+            | Self::ZeroAt(offset)
+            | Self::SetAt(offset, _) => *offset,
+            _ => 0,
+        }
+    }
+
+    pub fn at(&self, offset: isize) -> Option<Self> {
+        let at = match self {
+            Self::Add(a) => Self::AddAt(offset, *a),
+            Self::Sub(a) => Self::SubAt(offset, *a),
+            Self::Input(a) => Self::InputAt(offset, *a),
+            Self::Output(a) => Self::OutputAt(offset, *a),
+            Self::Zero => Self::ZeroAt(offset),
+            Self::Set(n) => Self::SetAt(offset, *n),
+            _ => return None,
+        };
+
+        Some(if offset == 0 { self.clone() } else { at })
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(self, Self::Open(_))
+    }
+
+    pub fn is_close(&self) -> bool {
+        matches!(self, Self::Close(_))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        matches!(self, Self::Zero)
+    }
+
+    pub fn is_set(&self) -> bool {
+        matches!(self, Self::Set(_))
+    }
+
+    pub fn leaves_zero(&self) -> bool {
+        matches!(
+            self,
+            Self::Close(_)
+                | Self::Zero
+                | Self::FindZeroLeft(_)
+                | Self::FindZeroRight(_)
+                | Self::ZeroAddLeft(_)
+                | Self::ZeroAddRight(_)
+                | Self::MulAdd(_)
+        )
+    }
+}
+
+impl fmt::Debug for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NOP => write!(f, "NOP"),
+            Self::Left(a) => write!(f, "Left({})", a),
+            Self::Right(a) => write!(f, "Right({})", a),
+            Self::Add(a) => write!(f, "Add({})", a),
+            Self::Sub(a) => write!(f, "Sub({})", a),
+            Self::Input(a) => write!(f, "Input({})", a),
+            Self::Output(a) => write!(f, "Output({})", a),
+            Self::Open(a) => write!(f, "Open({})", a),
+            Self::Close(a) => write!(f, "Close({})", a),
+            Self::Zero => write!(f, "Zero"),
+            Self::FindZeroLeft(a) => write!(f, "FindZeroLeft({})", a),
+            Self::FindZeroRight(a) => write!(f, "FindZeroRight({})", a),
+            Self::ZeroAddLeft(a) => write!(f, "ZeroAddLeft({})", a),
+            Self::ZeroAddRight(a) => write!(f, "ZeroAddRight({})", a),
+            Self::MulAdd(pairs) => write!(
+                f,
+                "MulAdd({})",
+                pairs
+                    .iter()
+                    .map(|(offset, factor)| format!("{}:{}", offset, factor))
+                    .join(",")
+            ),
+            Self::AddAt(o, a) => write!(f, "AddAt({},{})", o, a),
+            Self::SubAt(o, a) => write!(f, "SubAt({},{})", o, a),
+            Self::InputAt(o, a) => write!(f, "InputAt({},{})", o, a),
+            Self::OutputAt(o, a) => write!(f, "OutputAt({},{})", o, a),
+            Self::ZeroAt(o) => write!(f, "ZeroAt({})", o),
+            Self::Set(n) => write!(f, "Set({})", n),
+            Self::SetAt(o, n) => write!(f, "SetAt({},{})", o, n),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NOP => write!(f, "~"),
+            Self::Left(a) => write!(f, "<{}", a),
+            Self::Right(a) => write!(f, ">{}", a),
+            Self::Add(a) => write!(f, "+{}", a),
+            Self::Sub(a) => write!(f, "-{}", a),
+            Self::Input(a) => write!(f, ",{}", a),
+            Self::Output(a) => write!(f, ".{}", a),
+            Self::Open(_) => write!(f, "["),
+            Self::Close(_) => write!(f, "]"),
+            Self::Zero => write!(f, "!"),
+            Self::FindZeroLeft(a) => write!(f, "\\{}", a),
+            Self::FindZeroRight(a) => write!(f, "/{}", a),
+            Self::ZeroAddLeft(a) => write!(f, "w{}", a),
+            Self::ZeroAddRight(a) => write!(f, "m{}", a),
+            Self::MulAdd(pairs) => write!(
+                f,
+                "*({})",
+                pairs
+                    .iter()
+                    .map(|(offset, factor)| format!("{}:{}", offset, factor))
+                    .join(",")
+            ),
+            Self::AddAt(o, a) => write!(f, "+{}@{}", a, o),
+            Self::SubAt(o, a) => write!(f, "-{}@{}", a, o),
+            Self::InputAt(o, a) => write!(f, ",{}@{}", a, o),
+            Self::OutputAt(o, a) => write!(f, ".{}@{}", a, o),
+            Self::ZeroAt(o) => write!(f, "!@{}", o),
+            Self::Set(n) => write!(f, "={}", n),
+            Self::SetAt(o, n) => write!(f, "={}@{}", n, o),
+        }
+    }
+}
+
+impl convert::TryFrom<char> for Instruction {
+    type Error = ();
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        match c {
+            '<' => Ok(Self::Left(1)),
+            '>' => Ok(Self::Right(1)),
+            '+' => Ok(Self::Add(1)),
+            '-' => Ok(Self::Sub(1)),
+            ',' => Ok(Self::Input(1)),
+            '.' => Ok(Self::Output(1)),
+            '[' => Ok(Self::Open(1)),
+            ']' => Ok(Self::Close(1)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl convert::TryFrom<&char> for Instruction {
+    type Error = ();
+
+    fn try_from(c: &char) -> Result<Self, Self::Error> {
+        Self::try_from(*c)
+    }
+}
+
+impl convert::TryFrom<u8> for Instruction {
+    type Error = ();
+
+    fn try_from(b: u8) -> Result<Self, Self::Error> {
+        Self::try_from(b as char)
+    }
+}
+
+impl convert::TryFrom<&u8> for Instruction {
+    type Error = ();
+
+    fn try_from(b: &u8) -> Result<Self, Self::Error> {
+        Self::try_from(*b)
+    }
+}
+
+impl convert::TryFrom<&[Instruction]> for Instruction {
+    type Error = ();
+
+    fn try_from(s: &[Instruction]) -> Result<Self, Self::Error> {
+        if s.len() == 1 {
+            // zero, counting down or wrapping around
+            if (s[0].is_sub() || s[0].is_add()) && s[0].argument() == 1 {
+                return Ok(Instruction::Zero);
+            }
+
+            // Find zero left
+            if s[0].is_left() {
+                return Ok(Instruction::FindZeroLeft(s[0].argument()));
+            }
+
+            // Find zero right
+            if s[0].is_right() {
+                return Ok(Instruction::FindZeroRight(s[0].argument()));
+            }
+        } else if s.len() == 4 {
+            // Zero Add Left
+            if s[0].is_sub()
+                && s[0].argument() == 1
+                && s[1].is_left()
+                && s[2].is_add()
+                && s[2].argument() == 1
+                && s[3].is_right()
+                && s[1].argument() == s[3].argument()
+            {
+                return Ok(Instruction::ZeroAddLeft(s[1].argument()));
+            }
+
+            // Zero Add Right
+            if s[0].is_sub()
+                && s[0].argument() == 1
+                && s[1].is_right()
+                && s[2].is_add()
+                && s[2].argument() == 1
+                && s[3].is_left()
+                && s[1].argument() == s[3].argument()
+            {
+                return Ok(Instruction::ZeroAddRight(s[1].argument()));
+            }
+        }
+
+        // Multiply loop
+        if s.iter()
+            .all(|i| i.is_left() || i.is_right() || i.is_add() || i.is_sub() || i.is_nop())
+        {
+            let mut offset = 0;
+            let mut factors = BTreeMap::new();
+
+            for instruction in s {
+                match instruction {
+                    Self::Left(a) => offset -= *a as isize,
+                    Self::Right(a) => offset += *a as isize,
+                    Self::Add(a) => *factors.entry(offset).or_insert(0) += *a as isize,
+                    Self::Sub(a) => *factors.entry(offset).or_insert(0) -= *a as isize,
+                    _ => (),
+                }
+            }
+
+            // the counter has to step by one and the pointer has to return
+            let sign = match factors.remove(&0) {
+                Some(-1) if offset == 0 => 1,
+                Some(1) if offset == 0 => -1,
+                _ => return Err(()),
+            };
+
+            let pairs = factors
+                .into_iter()
+                .filter(|(_, factor)| *factor != 0)
+                .map(|(offset, factor)| (offset, factor * sign))
+                .collect::<Vec<_>>();
+
+            return Ok(if pairs.is_empty() {
+                Instruction::Zero
+            } else {
+                Instruction::MulAdd(pairs)
+            });
+        }
+
+        Err(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub beginning: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(beginning: usize, end: usize) -> Self {
+        Self { beginning, end }
+    }
+
+    pub fn join(&self, other: &Self) -> Self {
+        Self::new(self.beginning.min(other.beginning), self.end.max(other.end))
+    }
+}
+
+pub struct IR {
+    ir: Vec<Instruction>,
+    spans: Vec<Span>,
+}
+
+impl Default for IR {
+    fn default() -> Self {
+        Self {
+            ir: Vec::with_capacity(100),
+            spans: Vec::with_capacity(100),
+        }
+    }
+}
+
+impl ops::Index<usize> for IR {
+    type Output = Instruction;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.ir[index]
+    }
+}
+
+impl ops::IndexMut<usize> for IR {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.ir[index]
+    }
+}
+
+impl fmt::Debug for IR {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.ir.iter().map(|i| format!("{:?}", i)).join("\n")
+        )
+    }
+}
+
+impl fmt::Display for IR {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.ir.iter().join(""))
+    }
+}
+
+impl IR {
+    pub fn len(&self) -> usize {
+        self.ir.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ir.is_empty()
+    }
+
+    pub fn push(&mut self, value: Instruction, span: Span) {
+        self.ir.push(value);
+        self.spans.push(span);
+    }
+
+    pub fn pop(&mut self) -> Option<(Instruction, Span)> {
+        let instruction = self.ir.pop()?;
+        let span = self.spans.pop()?;
+        Some((instruction, span))
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.ir.truncate(len);
+        self.spans.truncate(len);
+    }
+
+    pub fn span(&self, index: usize) -> Span {
+        self.spans[index]
+    }
+
+    pub fn fingerprint(&self) -> u64 {
+        // the debug form includes jump targets
+        fnv1a(
+            self.ir
+                .iter()
+                .flat_map(|i| format!("{:?};", i).into_bytes()),
+        )
+    }
+
+    pub fn vec(&self) -> &Vec<Instruction> {
+        &self.ir
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    pub fn link(&mut self) {
+        let mut opens = Vec::new();
+
+        for index in 0..self.ir.len() {
+            match self.ir[index] {
+                Instruction::Open(_) => opens.push(index),
+                Instruction::Close(_) => {
+                    if let Some(open) = opens.pop() {
+                        self.ir[open] = Instruction::Open(index);
+                        self.ir[index] = Instruction::Close(open);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn fnv1a<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
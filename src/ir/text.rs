@@ -0,0 +1,212 @@
+use std::iter;
+use std::str;
+use thiserror::Error;
+
+use super::{Instruction, Span, VerifyError, IR};
+
+#[derive(Error, Debug)]
+pub enum TextError {
+    #[error("unexpected character: \"{}\"", .1)]
+    UnexpectedCharacter(usize, char),
+    #[error("expected a number")]
+    ExpectedNumber(usize),
+    #[error("number out of range")]
+    NumberOutOfRange(usize),
+    #[error("unbalanced bracket: \"{}\"", .1)]
+    MismatchedBracket(usize, char),
+    #[error("unexpected end of input")]
+    UnexpectedEnd(usize),
+    #[error("{}", .1)]
+    Invalid(usize, #[source] VerifyError),
+}
+
+impl TextError {
+    pub fn beginning(&self) -> usize {
+        match self {
+            Self::UnexpectedCharacter(position, _)
+            | Self::ExpectedNumber(position)
+            | Self::NumberOutOfRange(position)
+            | Self::MismatchedBracket(position, _)
+            | Self::UnexpectedEnd(position)
+            | Self::Invalid(position, _) => *position,
+        }
+    }
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    chars: iter::Peekable<str::CharIndices<'a>>,
+}
+
+impl<'a> Reader<'a> {
+    fn position(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.text.len(), |(index, _)| *index)
+    }
+
+    fn next_if(&mut self, expected: char) -> bool {
+        self.chars.next_if(|(_, c)| *c == expected).is_some()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), TextError> {
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((index, c)) => Err(TextError::UnexpectedCharacter(index, c)),
+            None => Err(TextError::UnexpectedEnd(self.text.len())),
+        }
+    }
+
+    fn digits(&mut self) -> &'a str {
+        let beginning = self.position();
+
+        while self.chars.next_if(|(_, c)| c.is_ascii_digit()).is_some() {}
+
+        &self.text[beginning..self.position()]
+    }
+
+    // counts may be left out, which makes plain brainfuck a subset
+    fn count(&mut self) -> Result<usize, TextError> {
+        let beginning = self.position();
+
+        match self.digits() {
+            "" => Ok(1),
+            digits => digits
+                .parse()
+                .map_err(|_| TextError::NumberOutOfRange(beginning)),
+        }
+    }
+
+    fn signed(&mut self) -> Result<isize, TextError> {
+        let beginning = self.position();
+        let negative = self.next_if('-');
+
+        match self.digits() {
+            "" => Err(TextError::ExpectedNumber(beginning)),
+            digits => format!("{}{}", if negative { "-" } else { "" }, digits)
+                .parse()
+                .map_err(|_| TextError::NumberOutOfRange(beginning)),
+        }
+    }
+
+    fn offset(&mut self) -> Result<Option<isize>, TextError> {
+        if self.next_if('@') {
+            self.signed().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn counted(
+        &mut self,
+        plain: fn(usize) -> Instruction,
+        at: fn(isize, usize) -> Instruction,
+    ) -> Result<Instruction, TextError> {
+        let a = self.count()?;
+
+        Ok(match self.offset()? {
+            Some(o) => at(o, a),
+            None => plain(a),
+        })
+    }
+
+    fn pairs(&mut self) -> Result<Vec<(isize, isize)>, TextError> {
+        let mut pairs = Vec::new();
+
+        self.expect('(')?;
+
+        if self.next_if(')') {
+            return Ok(pairs);
+        }
+
+        loop {
+            let offset = self.signed()?;
+            self.expect(':')?;
+            pairs.push((offset, self.signed()?));
+
+            if self.next_if(')') {
+                return Ok(pairs);
+            }
+
+            self.expect(',')?;
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while self.chars.next_if(|(_, c)| *c != '\n').is_some() {}
+    }
+}
+
+impl str::FromStr for IR {
+    type Err = TextError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut ir = IR::default();
+        let mut reader = Reader {
+            text,
+            chars: text.char_indices().peekable(),
+        };
+        let mut opens = Vec::new();
+
+        while let Some((beginning, c)) = reader.chars.next() {
+            let instruction = match c {
+                '#' => {
+                    reader.skip_line();
+                    continue;
+                }
+                c if c.is_whitespace() => continue,
+                '~' => Instruction::NOP,
+                '<' => Instruction::Left(reader.count()?),
+                '>' => Instruction::Right(reader.count()?),
+                '+' => reader.counted(Instruction::Add, Instruction::AddAt)?,
+                '-' => reader.counted(Instruction::Sub, Instruction::SubAt)?,
+                ',' => reader.counted(Instruction::Input, Instruction::InputAt)?,
+                '.' => reader.counted(Instruction::Output, Instruction::OutputAt)?,
+                '[' => {
+                    opens.push(beginning);
+                    Instruction::Open(0)
+                }
+                ']' => {
+                    opens
+                        .pop()
+                        .ok_or(TextError::MismatchedBracket(beginning, c))?;
+                    Instruction::Close(0)
+                }
+                '!' => match reader.offset()? {
+                    Some(o) => Instruction::ZeroAt(o),
+                    None => Instruction::Zero,
+                },
+                '\\' => Instruction::FindZeroLeft(reader.count()?),
+                '/' => Instruction::FindZeroRight(reader.count()?),
+                'w' => Instruction::ZeroAddLeft(reader.count()?),
+                'm' => Instruction::ZeroAddRight(reader.count()?),
+                '*' => Instruction::MulAdd(reader.pairs()?),
+                '=' => {
+                    let n = reader.signed()?;
+
+                    match reader.offset()? {
+                        Some(o) => Instruction::SetAt(o, n),
+                        None => Instruction::Set(n),
+                    }
+                }
+                _ => return Err(TextError::UnexpectedCharacter(beginning, c)),
+            };
+
+            let end = reader.position() - 1;
+            ir.push(instruction, Span::new(beginning, end));
+        }
+
+        if let Some(open) = opens.pop() {
+            return Err(TextError::MismatchedBracket(open, '['));
+        }
+
+        ir.link();
+
+        // zero counts and multiplications without targets parse, but the vm can
+        // not run them
+        match ir.verify() {
+            Ok(()) => Ok(ir),
+            Err(error) => Err(TextError::Invalid(ir.span(error.pc()).beginning, error)),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use thiserror::Error;
+
+use super::{Instruction, IR};
+
+#[derive(Error, Debug)]
+pub enum VerifyError {
+    #[error("jump at {} points at {} instead of {}", .0, .1, .2)]
+    JumpTarget(usize, usize, usize),
+    #[error("loop opened at {} is never closed", .0)]
+    UnmatchedOpen(usize),
+    #[error("loop closed at {} was never opened", .0)]
+    UnmatchedClose(usize),
+    #[error("degenerate instruction {:?} at {}", .1, .0)]
+    Degenerate(usize, Instruction),
+}
+
+impl VerifyError {
+    pub fn pc(&self) -> usize {
+        match self {
+            Self::JumpTarget(pc, _, _)
+            | Self::UnmatchedOpen(pc)
+            | Self::UnmatchedClose(pc)
+            | Self::Degenerate(pc, _) => *pc,
+        }
+    }
+}
+
+impl IR {
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let mut opens = Vec::new();
+
+        for (pc, instruction) in self.vec().iter().enumerate() {
+            if instruction.is_degenerate() {
+                return Err(VerifyError::Degenerate(pc, instruction.clone()));
+            }
+
+            match instruction {
+                Instruction::Open(_) => opens.push(pc),
+                Instruction::Close(target) => {
+                    let open = opens.pop().ok_or(VerifyError::UnmatchedClose(pc))?;
+
+                    if self[open].argument() != pc {
+                        return Err(VerifyError::JumpTarget(open, self[open].argument(), pc));
+                    }
+
+                    if *target != open {
+                        return Err(VerifyError::JumpTarget(pc, *target, open));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        match opens.pop() {
+            Some(open) => Err(VerifyError::UnmatchedOpen(open)),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::io;
+use std::ptr;
+
+// a private mapping that is writable while the code is copied in and
+// executable afterwards, never both
+pub(crate) struct ExecutableMemory {
+    pointer: *mut libc::c_void,
+    length: usize,
+}
+
+impl ExecutableMemory {
+    pub(crate) fn new(code: &[u8]) -> io::Result<Self> {
+        let length = code.len().max(1);
+
+        unsafe {
+            let pointer = libc::mmap(
+                ptr::null_mut(),
+                length,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+
+            if pointer == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+
+            let memory = Self { pointer, length };
+
+            ptr::copy_nonoverlapping(code.as_ptr(), pointer as *mut u8, code.len());
+
+            if libc::mprotect(pointer, length, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(memory)
+        }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.pointer as *const u8
+    }
+}
+
+impl Drop for ExecutableMemory {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.pointer, self.length);
+        }
+    }
+}
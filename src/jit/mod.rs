@@ -0,0 +1,181 @@
+mod memory;
+
+use std::io;
+use std::mem;
+
+use crate::backend::x86_64;
+use crate::ir::IR;
+use crate::virtual_machine::{Cell, EofBehavior, RuntimeError, Tape, TapeSize};
+use memory::ExecutableMemory;
+
+// the generated code reads and writes the first fields at the offsets in
+// backend::x86_64 and calls back into the runtime for everything else
+#[repr(C)]
+struct Runtime<'a, C: Cell> {
+    cells: *mut C,
+    length: usize,
+    head: usize,
+    pc: usize,
+    reserve: extern "C" fn(&mut Runtime<'a, C>, usize) -> bool,
+    input: extern "C" fn(&mut Runtime<'a, C>, usize, usize) -> bool,
+    output: extern "C" fn(&mut Runtime<'a, C>, u64, usize) -> bool,
+    tape: &'a mut Tape<C>,
+    writer: &'a mut dyn io::Write,
+    reader: &'a mut dyn io::Read,
+    eof: EofBehavior,
+    error: Option<io::Error>,
+}
+
+const _: () = {
+    assert!(mem::offset_of!(Runtime<'static, u8>, cells) == x86_64::CELLS as usize);
+    assert!(mem::offset_of!(Runtime<'static, u8>, length) == x86_64::LENGTH as usize);
+    assert!(mem::offset_of!(Runtime<'static, u8>, head) == x86_64::HEAD as usize);
+    assert!(mem::offset_of!(Runtime<'static, u8>, pc) == x86_64::PC as usize);
+    assert!(mem::offset_of!(Runtime<'static, u8>, reserve) == x86_64::RESERVE as usize);
+    assert!(mem::offset_of!(Runtime<'static, u8>, input) == x86_64::INPUT as usize);
+    assert!(mem::offset_of!(Runtime<'static, u8>, output) == x86_64::OUTPUT as usize);
+};
+
+extern "C" fn reserve<C: Cell>(runtime: &mut Runtime<C>, index: usize) -> bool {
+    if !runtime.tape.reserve(index) {
+        return false;
+    }
+
+    runtime.cells = runtime.tape.cells_mut().as_mut_ptr();
+    runtime.length = runtime.tape.len();
+
+    true
+}
+
+extern "C" fn input<C: Cell>(runtime: &mut Runtime<C>, index: usize, times: usize) -> bool {
+    for _ in 0..times {
+        if let Err(e) = runtime.writer.flush() {
+            runtime.error = Some(e);
+            return false;
+        }
+
+        let mut buffer = [0; 1];
+        match runtime.reader.read_exact(&mut buffer) {
+            Ok(_) => runtime.tape[index] = C::from_u8(buffer[0]),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => match runtime.eof {
+                EofBehavior::Error => {
+                    runtime.error = Some(e);
+                    return false;
+                }
+                EofBehavior::Unchanged => (),
+                EofBehavior::Zero => runtime.tape[index] = C::default(),
+                EofBehavior::MinusOne => runtime.tape[index] = C::MAX,
+            },
+            Err(e) => {
+                runtime.error = Some(e);
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+extern "C" fn output<C: Cell>(runtime: &mut Runtime<C>, value: u64, times: usize) -> bool {
+    for _ in 0..times {
+        if let Err(e) = runtime.writer.write_all(&[value as u8]) {
+            runtime.error = Some(e);
+            return false;
+        }
+    }
+
+    true
+}
+
+pub struct JIT<'a, C: Cell = u8> {
+    head: usize,
+    tape: Tape<C>,
+    eof: EofBehavior,
+    code: Option<ExecutableMemory>,
+    ir: &'a IR,
+}
+
+impl<'a> JIT<'a> {
+    pub fn new(ir: &'a IR) -> Self {
+        Self::new_generic(ir)
+    }
+}
+
+impl<'a, C: Cell> JIT<'a, C> {
+    pub fn new_generic(ir: &'a IR) -> Self {
+        Self {
+            head: 0,
+            tape: Tape::default(),
+            eof: EofBehavior::default(),
+            code: None,
+            ir,
+        }
+    }
+
+    pub fn with_tape_size(mut self, size: TapeSize) -> Self {
+        self.tape = Tape::new(size);
+        self
+    }
+
+    pub fn with_eof_behavior(mut self, eof: EofBehavior) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    pub fn tape(&self) -> &Tape<C> {
+        &self.tape
+    }
+
+    pub fn run<R, W>(&mut self, writer: &mut W, reader: &mut R) -> Result<(), RuntimeError>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        if self.code.is_none() {
+            let code = ExecutableMemory::new(&x86_64::compile::<C>(self.ir))
+                .map_err(|e| RuntimeError::Io(0, self.head, e))?;
+
+            self.code = Some(code);
+        }
+
+        let function: extern "C" fn(&mut Runtime<C>) -> u64 =
+            unsafe { mem::transmute(self.code.as_ref().unwrap().as_ptr()) };
+
+        let mut runtime = Runtime {
+            cells: self.tape.cells_mut().as_mut_ptr(),
+            length: self.tape.len(),
+            head: self.head,
+            pc: 0,
+            reserve: reserve::<C>,
+            input: input::<C>,
+            output: output::<C>,
+            tape: &mut self.tape,
+            writer,
+            reader,
+            eof: self.eof,
+            error: None,
+        };
+
+        let exit = function(&mut runtime);
+        let (pc, head) = (runtime.pc, runtime.head);
+
+        self.head = head;
+
+        match exit {
+            x86_64::FINISHED => Ok(()),
+            x86_64::POINTER_UNDERFLOW => Err(RuntimeError::PointerUnderflow(pc, head)),
+            x86_64::POINTER_OVERFLOW => Err(RuntimeError::PointerOverflow(pc, head)),
+            _ => match runtime.error.take() {
+                Some(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    Err(RuntimeError::UnexpectedEof(pc, head))
+                }
+                Some(e) => Err(RuntimeError::Io(pc, head, e)),
+                None => unreachable!(),
+            },
+        }
+    }
+}
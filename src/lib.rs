@@ -1,3 +1,9 @@
+pub mod backend;
+pub mod ir;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub mod jit;
+pub mod optimizer;
 pub mod parser;
+pub mod profiler;
+pub mod tracer;
 pub mod virtual_machine;
-pub mod ir;
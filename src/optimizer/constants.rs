@@ -0,0 +1,21 @@
+use super::Pass;
+use crate::ir::IR;
+use crate::parser::Warning;
+
+pub struct FoldConstants;
+
+impl Pass for FoldConstants {
+    fn name(&self) -> &'static str {
+        "fold-constants"
+    }
+
+    fn run(&self, ir: &mut IR, _warnings: &mut Vec<Warning>) {
+        super::merge(ir, |last, instruction| {
+            if last.is_zero() || last.is_set() {
+                last.combine(instruction)
+            } else {
+                None
+            }
+        });
+    }
+}
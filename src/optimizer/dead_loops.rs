@@ -0,0 +1,42 @@
+use super::Pass;
+use crate::ir::IR;
+use crate::parser::Warning;
+
+// assumes the program starts on a zeroed tape, so a loop at the start is
+// removed even if the host runs the ir from pc 0 on a tape it filled in with
+// VM::cell_mut or VM::restore, leave the pass out of the pipeline for that
+pub struct DeadLoops;
+
+impl Pass for DeadLoops {
+    fn name(&self) -> &'static str {
+        "dead-loops"
+    }
+
+    fn run(&self, ir: &mut IR, warnings: &mut Vec<Warning>) {
+        let mut live = IR::default();
+        let mut index = 0;
+
+        while index < ir.len() {
+            let instruction = &ir[index];
+
+            // the tape starts out zeroed and every loop exits on a zero cell
+            let dead =
+                instruction.is_open() && live.vec().last().is_none_or(|last| last.leaves_zero());
+
+            if dead {
+                let close = instruction.argument();
+                let span = ir.span(index).join(&ir.span(close));
+
+                warnings.push(Warning::DeadLoop(span.beginning, span.end));
+                index = close + 1;
+            } else {
+                live.push(instruction.clone(), ir.span(index));
+                index += 1;
+            }
+        }
+
+        live.link();
+
+        *ir = live;
+    }
+}
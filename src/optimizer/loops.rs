@@ -0,0 +1,47 @@
+use std::convert::TryFrom;
+
+use super::Pass;
+use crate::ir::{Instruction, IR};
+use crate::parser::Warning;
+
+pub struct LoopPatterns;
+
+impl Pass for LoopPatterns {
+    fn name(&self) -> &'static str {
+        "loop-patterns"
+    }
+
+    fn run(&self, ir: &mut IR, _warnings: &mut Vec<Warning>) {
+        let mut replaced = IR::default();
+        let mut opens = Vec::new();
+
+        for (instruction, span) in ir.vec().iter().zip(ir.spans()) {
+            match instruction {
+                Instruction::Open(_) => {
+                    opens.push(replaced.len());
+                    replaced.push(instruction.clone(), *span);
+                }
+                Instruction::Close(_) => {
+                    let meta_instruction = opens.pop().and_then(|open| {
+                        Instruction::try_from(&replaced.vec()[open + 1..])
+                            .ok()
+                            .map(|meta_instruction| (open, meta_instruction))
+                    });
+
+                    if let Some((open, meta_instruction)) = meta_instruction {
+                        let span = replaced.span(open).join(span);
+                        replaced.truncate(open);
+                        replaced.push(meta_instruction, span);
+                    } else {
+                        replaced.push(instruction.clone(), *span);
+                    }
+                }
+                _ => replaced.push(instruction.clone(), *span),
+            }
+        }
+
+        replaced.link();
+
+        *ir = replaced;
+    }
+}
@@ -0,0 +1,108 @@
+mod constants;
+mod dead_loops;
+mod loops;
+mod offsets;
+mod run_length;
+
+pub use constants::FoldConstants;
+pub use dead_loops::DeadLoops;
+pub use loops::LoopPatterns;
+pub use offsets::SinkPointerMoves;
+pub use run_length::RunLength;
+
+use crate::ir::{Instruction, VerifyError, IR};
+use crate::parser::Warning;
+
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    fn run(&self, ir: &mut IR, warnings: &mut Vec<Warning>);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    O0,
+    O1,
+    #[default]
+    O2,
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl Pipeline {
+    pub fn new(level: OptLevel) -> Self {
+        match level {
+            OptLevel::O0 => Self::default(),
+            OptLevel::O1 => Self::default()
+                .with_pass(DeadLoops)
+                .with_pass(RunLength)
+                .with_pass(LoopPatterns),
+            OptLevel::O2 => Self::new(OptLevel::O1)
+                .with_pass(FoldConstants)
+                .with_pass(SinkPointerMoves),
+        }
+    }
+
+    pub fn with_pass<P: Pass + 'static>(mut self, pass: P) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    pub fn run(&self, ir: &mut IR) -> Result<Vec<Warning>, VerifyError> {
+        let mut warnings = Vec::new();
+
+        for pass in &self.passes {
+            pass.run(ir, &mut warnings);
+
+            // debug builds stop at the pass that broke the IR, before the
+            // next one runs on it
+            if cfg!(debug_assertions) {
+                ir.verify()?;
+            }
+        }
+
+        // otherwise the result is checked once, unless the last pass already did
+        if !cfg!(debug_assertions) || self.passes.is_empty() {
+            ir.verify()?;
+        }
+
+        Ok(warnings)
+    }
+}
+
+// merges each instruction into the one before it whenever `f` allows, dropping
+// anything that cancels out
+fn merge<F>(ir: &mut IR, f: F)
+where
+    F: Fn(&Instruction, &Instruction) -> Option<Instruction>,
+{
+    let mut merged = IR::default();
+
+    for (instruction, span) in ir.vec().iter().zip(ir.spans()) {
+        if instruction.is_nop() {
+            continue;
+        }
+
+        match merged.vec().last().and_then(|last| f(last, instruction)) {
+            Some(Instruction::NOP) => {
+                merged.pop();
+            }
+            Some(combined) => {
+                let (_, last) = merged.pop().unwrap();
+                merged.push(combined, last.join(span));
+            }
+            None => merged.push(instruction.clone(), *span),
+        }
+    }
+
+    merged.link();
+
+    *ir = merged;
+}
@@ -0,0 +1,55 @@
+use super::Pass;
+use crate::ir::{Instruction, Span, IR};
+use crate::parser::Warning;
+
+pub struct SinkPointerMoves;
+
+impl Pass for SinkPointerMoves {
+    fn name(&self) -> &'static str {
+        "sink-pointer-moves"
+    }
+
+    fn run(&self, ir: &mut IR, _warnings: &mut Vec<Warning>) {
+        let mut sunk = IR::default();
+        let mut offset = 0;
+        let mut moves: Option<Span> = None;
+
+        for (instruction, span) in ir.vec().iter().zip(ir.spans()) {
+            match instruction {
+                Instruction::NOP => (),
+                Instruction::Left(a) | Instruction::Right(a) => {
+                    offset += if instruction.is_left() {
+                        -(*a as isize)
+                    } else {
+                        *a as isize
+                    };
+                    moves = Some(moves.map_or(*span, |m| m.join(span)));
+                }
+                _ => {
+                    if let Some(at) = instruction.at(offset) {
+                        sunk.push(at, *span);
+                    } else {
+                        push_move(&mut sunk, offset, moves.take());
+                        offset = 0;
+                        sunk.push(instruction.clone(), *span);
+                    }
+                }
+            }
+        }
+
+        push_move(&mut sunk, offset, moves);
+        sunk.link();
+
+        *ir = sunk;
+    }
+}
+
+fn push_move(ir: &mut IR, offset: isize, span: Option<Span>) {
+    let span = span.unwrap_or_default();
+
+    if offset < 0 {
+        ir.push(Instruction::Left(offset.unsigned_abs()), span);
+    } else if offset > 0 {
+        ir.push(Instruction::Right(offset as usize), span);
+    }
+}
@@ -0,0 +1,15 @@
+use super::Pass;
+use crate::ir::IR;
+use crate::parser::Warning;
+
+pub struct RunLength;
+
+impl Pass for RunLength {
+    fn name(&self) -> &'static str {
+        "run-length"
+    }
+
+    fn run(&self, ir: &mut IR, _warnings: &mut Vec<Warning>) {
+        super::merge(ir, |last, instruction| last.combine(instruction));
+    }
+}
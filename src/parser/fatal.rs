@@ -1,15 +1,19 @@
 use thiserror::Error;
 
+use crate::ir::VerifyError;
+
 #[derive(Error, Debug)]
 pub enum Fatal {
     #[error("unbalanced bracket: \"{}\"", .1)]
     MismatchedBracket(usize, String),
+    #[error("optimizer left invalid IR: {}", .1)]
+    InvalidIr(usize, #[source] VerifyError),
 }
 
 impl Fatal {
     pub fn beginning(&self) -> usize {
         match self {
-            Self::MismatchedBracket(position, _) => *position,
+            Self::MismatchedBracket(position, _) | Self::InvalidIr(position, _) => *position,
         }
     }
 }
@@ -1,7 +1,10 @@
 mod fatal;
 mod warning;
 
-use crate::ir::{Instruction, IR};
+pub use warning::Warning;
+
+use crate::ir::{Instruction, Span, IR};
+use crate::optimizer::{OptLevel, Pipeline};
 use fatal::Fatal;
 use std::convert::TryFrom;
 use std::fmt;
@@ -17,9 +20,9 @@ struct InstructionWithIndex {
 impl InstructionWithIndex {
     fn new(instruction: Instruction, beginning: usize, end: usize) -> Self {
         Self {
-            instruction: instruction,
-            beginning: beginning,
-            end: end,
+            instruction,
+            beginning,
+            end,
         }
     }
 }
@@ -33,14 +36,12 @@ where
     fn try_from(iter: &mut iter::Peekable<I>) -> Result<Self, Self::Error> {
         while let Some((beginning, c)) = iter.next() {
             if let Ok(mut instruction) = Instruction::try_from(c) {
-                let mut end = beginning.clone();
+                let mut end = beginning;
 
                 while let Some(next_instruction) = iter
                     .peek()
-                    .map(|(_, c)| Instruction::try_from(c).ok())
-                    .flatten()
-                    .map(|e| instruction.combine(&e))
-                    .flatten()
+                    .and_then(|(_, c)| Instruction::try_from(c).ok())
+                    .and_then(|e| instruction.combine(&e))
                 {
                     instruction = next_instruction;
                     end = iter.next().unwrap().0;
@@ -50,13 +51,14 @@ where
             }
         }
 
-        return Err(());
+        Err(())
     }
 }
 
 pub struct Parser {
     ir: IR,
-    warnings: Vec<warning::Warning>,
+    warnings: Vec<Warning>,
+    pipeline: Pipeline,
 }
 
 impl Default for Parser {
@@ -64,6 +66,7 @@ impl Default for Parser {
         Self {
             ir: IR::default(),
             warnings: Vec::with_capacity(10),
+            pipeline: Pipeline::new(OptLevel::default()),
         }
     }
 }
@@ -71,45 +74,51 @@ impl Default for Parser {
 pub type ParseResult = Result<(), Fatal>;
 
 impl Parser {
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
     pub fn parse(&mut self, string: &str) -> ParseResult {
         let mut brackets = Vec::with_capacity(20);
         let mut indices = string.char_indices().peekable();
 
-        while let Ok(instruction_with_context) = InstructionWithIndex::try_from(&mut indices) {
-            let mut instruction = instruction_with_context.instruction;
-            let beginning = instruction_with_context.beginning;
-            let end = instruction_with_context.end;
-            let instruction_index = self.ir.len();
-
-            if instruction.is_open() {
-                brackets.push(JumpIndex::new(
-                    instruction.clone(),
-                    instruction_index,
-                    beginning,
+        // runs are only combined to spot sequences that cancel out, the ir
+        // keeps one instruction per character until the pipeline runs
+        while let Ok(run) = InstructionWithIndex::try_from(&mut indices) {
+            if run.instruction.is_nop() {
+                self.warnings.push(Warning::NOP(
+                    run.beginning,
+                    run.end,
+                    String::from(&string[run.beginning..=run.end]),
                 ));
-            } else if instruction.is_close() {
-                let open = brackets
-                    .pop()
-                    .ok_or_else(|| Fatal::MismatchedBracket(beginning, instruction.to_string()))?
-                    .jump_index;
-
-                if let Ok(meta_instruction) = Instruction::try_from(&self.ir.vec()[open + 1..]) {
-                    self.ir.mut_vec().drain(open..);
-                    instruction = meta_instruction;
-                } else {
-                    // normal loop
+            }
+
+            for (beginning, c) in string[run.beginning..=run.end].char_indices() {
+                let beginning = run.beginning + beginning;
+                let mut instruction = Instruction::try_from(c).unwrap();
+                let instruction_index = self.ir.len();
+
+                if instruction.is_open() {
+                    brackets.push(JumpIndex::new(
+                        instruction.clone(),
+                        instruction_index,
+                        beginning,
+                    ));
+                } else if instruction.is_close() {
+                    let open = brackets
+                        .pop()
+                        .ok_or_else(|| {
+                            Fatal::MismatchedBracket(beginning, instruction.to_string())
+                        })?
+                        .jump_index;
+
                     self.ir[open].modify_argument(|_| instruction_index);
                     instruction.modify_argument(|_| open);
                 }
-            } else if instruction.is_nop() {
-                self.warnings.push(warning::Warning::NOP(
-                    beginning,
-                    end,
-                    String::from(&string[beginning..=end]),
-                ));
-            }
 
-            self.ir.push(instruction);
+                self.ir.push(instruction, Span::new(beginning, beginning));
+            }
         }
 
         if let Some(mismatched) = brackets.pop() {
@@ -118,17 +127,27 @@ impl Parser {
                 mismatched.instruction.to_string(),
             ))
         } else {
-            Ok(())
+            match self.pipeline.run(&mut self.ir) {
+                Ok(warnings) => {
+                    self.warnings.extend(warnings);
+                    Ok(())
+                }
+                Err(error) => Err(Fatal::InvalidIr(self.ir.span(error.pc()).beginning, error)),
+            }
         }
     }
 
-    pub fn warnings(&self) -> &Vec<warning::Warning> {
+    pub fn warnings(&self) -> &Vec<Warning> {
         &self.warnings
     }
 
     pub fn ir(&self) -> &IR {
         &self.ir
     }
+
+    pub fn into_ir(self) -> IR {
+        self.ir
+    }
 }
 
 impl fmt::Debug for Parser {
@@ -152,9 +171,9 @@ struct JumpIndex {
 impl JumpIndex {
     pub fn new(instruction: Instruction, jump_index: usize, index: usize) -> Self {
         Self {
-            instruction: instruction,
-            jump_index: jump_index,
-            index: index,
+            instruction,
+            jump_index,
+            index,
         }
     }
 }
@@ -1,25 +1,28 @@
 use std::fmt;
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum Warning {
     NOP(usize, usize, String),
+    DeadLoop(usize, usize),
 }
 
 impl Warning {
     pub fn beginning(&self) -> usize {
         match self {
-            Self::NOP(beginning, _, _) => *beginning,
+            Self::NOP(beginning, _, _) | Self::DeadLoop(beginning, _) => *beginning,
         }
     }
 
     pub fn end(&self) -> usize {
         match self {
-            Self::NOP(_, end, _) => *end,
+            Self::NOP(_, end, _) | Self::DeadLoop(_, end) => *end,
         }
     }
 
     pub fn line(&self) -> String {
         match self {
             Self::NOP(_, _, line) => line.clone(),
+            Self::DeadLoop(..) => String::new(),
         }
     }
 }
@@ -28,6 +31,7 @@ impl fmt::Display for Warning {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::NOP(_, _, ir_instruction) => write!(f, "no operation: \"{}\"", ir_instruction),
+            Self::DeadLoop(..) => write!(f, "loop never runs because the cell is always zero"),
         }
     }
 }
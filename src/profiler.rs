@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use itertools::Itertools;
+use line_col::LineColLookup;
+
+use crate::ir::{Instruction, Span, IR};
+
+const SNIPPET_LENGTH: usize = 24;
+
+pub struct Loop {
+    pub open: usize,
+    pub close: usize,
+    pub instructions: u64,
+    pub iterations: u64,
+}
+
+pub struct Profile {
+    counts: Vec<u64>,
+    iterations: Vec<u64>,
+}
+
+impl Profile {
+    pub fn new(len: usize) -> Self {
+        Self {
+            counts: vec![0; len],
+            iterations: vec![0; len],
+        }
+    }
+
+    pub(crate) fn count(&mut self, pc: usize) {
+        self.counts[pc] += 1;
+    }
+
+    pub(crate) fn iterate(&mut self, open: usize) {
+        self.iterations[open] += 1;
+    }
+
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn loops(&self, ir: &IR) -> Vec<Loop> {
+        ir.vec()
+            .iter()
+            .enumerate()
+            .filter_map(|(open, instruction)| match instruction {
+                Instruction::Open(close) => Some(Loop {
+                    open,
+                    close: *close,
+                    instructions: self.counts[open..=*close].iter().sum(),
+                    iterations: self.iterations[open],
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn report(&self, ir: &IR, source: &str, limit: usize) -> String {
+        let lookup = LineColLookup::new(source);
+        let total = self.total().max(1);
+        let mut report = String::new();
+
+        writeln!(report, "executed {} instructions", self.total()).unwrap();
+        writeln!(report).unwrap();
+        writeln!(
+            report,
+            "{:>12} {:>7} {:>7}  {:<18} {:<10} source",
+            "count", "%", "pc", "instruction", "location"
+        )
+        .unwrap();
+
+        for (pc, count) in self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .sorted_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(&b.0)))
+            .take(limit)
+        {
+            writeln!(
+                report,
+                "{:>12} {:>6.2}% {:>7}  {:<18} {:<10} {}",
+                count,
+                *count as f64 * 100.0 / total as f64,
+                pc,
+                format!("{:?}", ir[pc]),
+                location(&lookup, ir.span(pc)),
+                snippet(source, ir.span(pc)),
+            )
+            .unwrap();
+        }
+
+        writeln!(report).unwrap();
+        writeln!(
+            report,
+            "{:>12} {:>7} {:>12} {:>11}  {:<10} source",
+            "count", "%", "iterations", "pc", "location"
+        )
+        .unwrap();
+
+        for l in self
+            .loops(ir)
+            .iter()
+            .filter(|l| l.instructions > 0)
+            .sorted_by(|a, b| {
+                b.instructions
+                    .cmp(&a.instructions)
+                    .then(a.open.cmp(&b.open))
+            })
+            .take(limit)
+        {
+            let span = ir.span(l.open).join(&ir.span(l.close));
+
+            writeln!(
+                report,
+                "{:>12} {:>6.2}% {:>12} {:>11}  {:<10} {}",
+                l.instructions,
+                l.instructions as f64 * 100.0 / total as f64,
+                l.iterations,
+                format!("{}-{}", l.open, l.close),
+                location(&lookup, span),
+                snippet(source, span),
+            )
+            .unwrap();
+        }
+
+        report
+    }
+
+    pub fn folded(&self, ir: &IR, source: &str) -> String {
+        let lookup = LineColLookup::new(source);
+        let mut stacks = BTreeMap::new();
+        let mut frames = Vec::new();
+
+        for (pc, instruction) in ir.vec().iter().enumerate() {
+            if let Instruction::Open(_) = instruction {
+                frames.push(format!("loop@{}", location(&lookup, ir.span(pc))));
+            }
+
+            if self.counts[pc] > 0 {
+                let leaf = format!("{:?}@{}", instruction, location(&lookup, ir.span(pc)));
+                let stack = frames.iter().chain(Some(&leaf)).join(";");
+
+                *stacks.entry(stack).or_insert(0) += self.counts[pc];
+            }
+
+            if let Instruction::Close(_) = instruction {
+                frames.pop();
+            }
+        }
+
+        stacks
+            .iter()
+            .map(|(stack, count)| format!("{} {}\n", stack, count))
+            .collect()
+    }
+}
+
+fn location(lookup: &LineColLookup, span: Span) -> String {
+    let (line, column) = lookup.get(span.beginning);
+    format!("{}:{}", line, column)
+}
+
+fn snippet(source: &str, span: Span) -> String {
+    let text = source
+        .get(span.beginning..=span.end)
+        .unwrap_or_default()
+        .split_whitespace()
+        .join(" ");
+
+    if text.chars().count() > SNIPPET_LENGTH {
+        format!(
+            "{}...",
+            text.chars().take(SNIPPET_LENGTH).collect::<String>()
+        )
+    } else {
+        text
+    }
+}
@@ -0,0 +1,59 @@
+use std::io;
+
+use crate::ir::IR;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    #[default]
+    Text,
+    JsonLines,
+}
+
+pub struct Tracer<'a> {
+    writer: Box<dyn io::Write + 'a>,
+    format: TraceFormat,
+}
+
+impl<'a> Tracer<'a> {
+    pub fn new<W: io::Write + 'a>(writer: W, format: TraceFormat) -> Self {
+        Self {
+            writer: Box::new(writer),
+            format,
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        ir: &IR,
+        pc: usize,
+        head: usize,
+        offset: isize,
+        before: u64,
+        after: u64,
+    ) -> io::Result<()> {
+        let (instruction, span) = (&ir[pc], ir.span(pc));
+
+        match self.format {
+            TraceFormat::Text => writeln!(
+                self.writer,
+                "{:>8} {:<18} {:<13} head={:<8} offset={:<6} {} -> {}",
+                pc,
+                format!("{:?}", instruction),
+                format!("{}-{}", span.beginning, span.end),
+                head,
+                offset,
+                before,
+                after
+            ),
+            TraceFormat::JsonLines => writeln!(
+                self.writer,
+                "{{\"pc\":{},\"instruction\":\"{:?}\",\"beginning\":{},\"end\":{},\"head\":{},\"offset\":{},\"before\":{},\"after\":{}}}",
+                pc, instruction, span.beginning, span.end, head, offset, before, after
+            ),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
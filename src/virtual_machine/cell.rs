@@ -0,0 +1,114 @@
+use std::fmt;
+
+use super::scan;
+
+pub trait Cell: Copy + Default + Eq + fmt::Debug + fmt::Display {
+    const BITS: u32;
+
+    const MAX: Self;
+
+    fn from_usize(value: usize) -> Self;
+
+    fn from_u8(value: u8) -> Self;
+
+    fn from_isize(value: isize) -> Self;
+
+    fn to_u8(self) -> u8;
+
+    fn from_u64(value: u64) -> Self;
+
+    fn to_u64(self) -> u64;
+
+    fn wrapping_add(self, other: Self) -> Self;
+
+    fn wrapping_sub(self, other: Self) -> Self;
+
+    fn wrapping_mul(self, other: Self) -> Self;
+
+    fn is_zero(self) -> bool {
+        self == Self::default()
+    }
+
+    fn find_zero_right(cells: &[Self], start: usize, stride: usize) -> Option<usize> {
+        (start..cells.len())
+            .step_by(stride)
+            .find(|&index| cells[index].is_zero())
+    }
+
+    fn find_zero_left(cells: &[Self], start: usize, stride: usize) -> Option<usize> {
+        let mut index = start;
+
+        loop {
+            if cells[index].is_zero() {
+                return Some(index);
+            }
+
+            index = index.checked_sub(stride)?;
+        }
+    }
+}
+
+macro_rules! impl_cell {
+    ($($t:ty { $($scan:item)* }),*) => {
+        $(
+            impl Cell for $t {
+                const BITS: u32 = <$t>::BITS;
+
+                const MAX: Self = <$t>::MAX;
+
+                fn from_usize(value: usize) -> Self {
+                    value as $t
+                }
+
+                fn from_u8(value: u8) -> Self {
+                    value as $t
+                }
+
+                fn from_isize(value: isize) -> Self {
+                    value as $t
+                }
+
+                fn to_u8(self) -> u8 {
+                    self as u8
+                }
+
+                fn from_u64(value: u64) -> Self {
+                    value as $t
+                }
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn wrapping_add(self, other: Self) -> Self {
+                    <$t>::wrapping_add(self, other)
+                }
+
+                fn wrapping_sub(self, other: Self) -> Self {
+                    <$t>::wrapping_sub(self, other)
+                }
+
+                fn wrapping_mul(self, other: Self) -> Self {
+                    <$t>::wrapping_mul(self, other)
+                }
+
+                $($scan)*
+            }
+        )*
+    };
+}
+
+impl_cell!(
+    u8 {
+        fn find_zero_right(cells: &[Self], start: usize, stride: usize) -> Option<usize> {
+            scan::find_zero_right(cells, start, stride)
+        }
+
+        fn find_zero_left(cells: &[Self], start: usize, stride: usize) -> Option<usize> {
+            scan::find_zero_left(cells, start, stride)
+        }
+    },
+    u16 {},
+    u32 {},
+    u64 {}
+);
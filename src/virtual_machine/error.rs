@@ -0,0 +1,34 @@
+use std::io;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RuntimeError {
+    #[error("pointer moved left of the first cell (pc {}, head {})", .0, .1)]
+    PointerUnderflow(usize, usize),
+    #[error("pointer moved past the end of the tape (pc {}, head {})", .0, .1)]
+    PointerOverflow(usize, usize),
+    #[error("i/o error: {} (pc {}, head {})", .2, .0, .1)]
+    Io(usize, usize, #[source] io::Error),
+    #[error("unexpected end of input (pc {}, head {})", .0, .1)]
+    UnexpectedEof(usize, usize),
+}
+
+impl RuntimeError {
+    pub fn pc(&self) -> usize {
+        match self {
+            Self::PointerUnderflow(pc, _)
+            | Self::PointerOverflow(pc, _)
+            | Self::Io(pc, _, _)
+            | Self::UnexpectedEof(pc, _) => *pc,
+        }
+    }
+
+    pub fn head(&self) -> usize {
+        match self {
+            Self::PointerUnderflow(_, head)
+            | Self::PointerOverflow(_, head)
+            | Self::Io(_, head, _)
+            | Self::UnexpectedEof(_, head) => *head,
+        }
+    }
+}
@@ -0,0 +1,589 @@
+mod cell;
+mod error;
+mod scan;
+mod snapshot;
+mod tape;
+
+use std::io;
+use std::iter;
+
+use crate::ir::{Instruction, IR};
+use crate::profiler::Profile;
+use crate::tracer::Tracer;
+pub use cell::Cell;
+pub use error::RuntimeError;
+pub use snapshot::{Snapshot, SnapshotError};
+pub use tape::{Tape, TapeSize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Finished,
+    OutOfFuel,
+    WaitingForIo,
+    Input,
+    Output,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Finished,
+    Executed(usize),
+    Input(usize),
+    Output(usize),
+    WaitingForIo(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopAt {
+    Input,
+    Output,
+    InputOrOutput,
+}
+
+impl StopAt {
+    fn input(&self) -> bool {
+        matches!(self, Self::Input | Self::InputOrOutput)
+    }
+
+    fn output(&self) -> bool {
+        matches!(self, Self::Output | Self::InputOrOutput)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum EofBehavior {
+    #[default]
+    Error,
+    Unchanged,
+    Zero,
+    MinusOne,
+}
+
+pub struct VM<'a, C: Cell = u8> {
+    pc: usize,
+    head: usize,
+    repeat: usize,
+    tape: Tape<C>,
+    eof: EofBehavior,
+    profile: Option<Profile>,
+    tracer: Option<Tracer<'a>>,
+    ir: &'a IR,
+}
+
+impl<'a> VM<'a> {
+    pub fn new(ir: &'a IR) -> Self {
+        Self::new_generic(ir)
+    }
+}
+
+impl<'a, C: Cell> VM<'a, C> {
+    pub fn cell(&self) -> C {
+        self.tape[self.head]
+    }
+
+    pub fn cell_mut(&mut self) -> &mut C {
+        &mut self.tape[self.head]
+    }
+
+    pub fn head_to(&mut self, position: usize) -> Result<(), RuntimeError> {
+        if !self.tape.reserve(position) {
+            return Err(RuntimeError::PointerOverflow(self.pc, self.head));
+        }
+
+        self.head = position;
+        Ok(())
+    }
+
+    pub fn jump_to(&mut self, position: usize) {
+        self.pc = position
+    }
+
+    pub fn increase_pc(&mut self) {
+        self.pc += 1
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    pub fn done(&self) -> bool {
+        self.pc >= self.ir.len()
+    }
+
+    pub fn tape(&self) -> &Tape<C> {
+        &self.tape
+    }
+
+    pub fn new_generic(ir: &'a IR) -> Self {
+        Self {
+            pc: 0,
+            head: 0,
+            repeat: 0,
+            tape: Tape::default(),
+            eof: EofBehavior::default(),
+            profile: None,
+            tracer: None,
+            ir,
+        }
+    }
+
+    pub fn with_tape_size(mut self, size: TapeSize) -> Self {
+        self.tape = Tape::new(size);
+        self
+    }
+
+    pub fn snapshot(&self) -> Snapshot<C> {
+        let cells = self.tape.cells();
+        let used = cells
+            .iter()
+            .rposition(|cell| !cell.is_zero())
+            .map_or(0, |last| last + 1);
+
+        Snapshot {
+            fingerprint: self.ir.fingerprint(),
+            pc: self.pc,
+            head: self.head,
+            repeat: self.repeat,
+            cells: cells[..used].to_vec(),
+        }
+    }
+
+    // a tape that is not zeroed at pc 0 needs ir built without DeadLoops,
+    // which removes a loop at the start of the program
+    pub fn restore(&mut self, snapshot: &Snapshot<C>) -> Result<(), SnapshotError> {
+        if snapshot.fingerprint != self.ir.fingerprint() {
+            return Err(SnapshotError::Fingerprint);
+        }
+
+        if snapshot.pc > self.ir.len() {
+            return Err(SnapshotError::PcPastEnd(snapshot.pc));
+        }
+
+        if snapshot.head >= self.tape.size().limit() {
+            return Err(SnapshotError::HeadPastEnd(snapshot.head));
+        }
+
+        if !self.tape.replace(snapshot.cells.clone(), snapshot.head) {
+            return Err(SnapshotError::TapeTooLarge(snapshot.cells.len()));
+        }
+
+        self.pc = snapshot.pc;
+        self.head = snapshot.head;
+        self.repeat = snapshot.repeat;
+
+        Ok(())
+    }
+
+    pub fn with_profiler(mut self) -> Self {
+        self.profile = Some(Profile::new(self.ir.len()));
+        self
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub fn with_tracer(mut self, tracer: Tracer<'a>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        match &mut self.tracer {
+            Some(tracer) => tracer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn with_eof_behavior(mut self, eof: EofBehavior) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    fn left(&self, a: usize) -> Result<usize, RuntimeError> {
+        self.head
+            .checked_sub(a)
+            .ok_or(RuntimeError::PointerUnderflow(self.pc, self.head))
+    }
+
+    fn right(&mut self, a: usize) -> Result<usize, RuntimeError> {
+        match self.head.checked_add(a) {
+            Some(position) if self.tape.reserve(position) => Ok(position),
+            _ => Err(RuntimeError::PointerOverflow(self.pc, self.head)),
+        }
+    }
+
+    fn offset(&mut self, offset: isize) -> Result<usize, RuntimeError> {
+        if offset < 0 {
+            self.left(offset.unsigned_abs())
+        } else {
+            self.right(offset as usize)
+        }
+    }
+
+    fn input<R, W>(
+        &mut self,
+        target: usize,
+        times: usize,
+        step: bool,
+        writer: &mut W,
+        reader: &mut R,
+    ) -> Result<bool, RuntimeError>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        while self.repeat < times {
+            writer.flush().map_err(|e| self.io_error(target, e))?;
+            let mut buffer = [0; 1];
+            match reader.read_exact(&mut buffer) {
+                Ok(_) => self.tape[target] = C::from_u8(buffer[0]),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => match self.eof {
+                    EofBehavior::Error => return Err(self.io_error(target, e)),
+                    EofBehavior::Unchanged => (),
+                    EofBehavior::Zero => self.tape[target] = C::default(),
+                    EofBehavior::MinusOne => self.tape[target] = C::MAX,
+                },
+                Err(e) => return Err(self.io_error(target, e)),
+            }
+            self.repeat += 1;
+
+            // stepping reads one byte at a time and stays on the instruction
+            // until it has all of them
+            if step && self.repeat < times {
+                return Ok(false);
+            }
+        }
+        self.repeat = 0;
+
+        Ok(true)
+    }
+
+    fn output<W: io::Write>(
+        &mut self,
+        target: usize,
+        times: usize,
+        writer: &mut W,
+    ) -> Result<(), RuntimeError> {
+        while self.repeat < times {
+            writer
+                .write_all(&[self.tape[target].to_u8()])
+                .map_err(|e| self.io_error(target, e))?;
+            self.repeat += 1;
+        }
+        self.repeat = 0;
+
+        Ok(())
+    }
+
+    // offset instructions report the cell they read or write, the same
+    // head the unoptimized program would have had
+    fn io_error(&self, target: usize, error: io::Error) -> RuntimeError {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => RuntimeError::UnexpectedEof(self.pc, target),
+            _ => RuntimeError::Io(self.pc, target, error),
+        }
+    }
+
+    pub fn run<R, W>(&mut self, writer: &mut W, reader: &mut R) -> Result<(), RuntimeError>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        if self.profile.is_some() || self.tracer.is_some() {
+            while !self.done() {
+                self.execute(false, writer, reader)?;
+            }
+        } else {
+            while !self.done() {
+                self.dispatch(false, writer, reader)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn run_with_fuel<R, W>(
+        &mut self,
+        fuel: usize,
+        writer: &mut W,
+        reader: &mut R,
+    ) -> Result<Status, RuntimeError>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        for _ in 0..fuel {
+            match self.step(writer, reader)? {
+                Event::Finished => return Ok(Status::Finished),
+                Event::WaitingForIo(_) => return Ok(Status::WaitingForIo),
+                _ => (),
+            }
+        }
+
+        Ok(if self.done() {
+            Status::Finished
+        } else {
+            Status::OutOfFuel
+        })
+    }
+
+    pub fn run_until<R, W>(
+        &mut self,
+        stop: StopAt,
+        writer: &mut W,
+        reader: &mut R,
+    ) -> Result<Status, RuntimeError>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        // stops before an input instruction, after the output, so the caller
+        // has to step over the input before calling this again
+        loop {
+            if stop.input() && !self.done() && self.ir[self.pc].is_input() {
+                return Ok(Status::Input);
+            }
+
+            match self.step(writer, reader)? {
+                Event::Finished => return Ok(Status::Finished),
+                Event::WaitingForIo(_) => return Ok(Status::WaitingForIo),
+                Event::Output(_) if stop.output() => return Ok(Status::Output),
+                _ => (),
+            }
+        }
+    }
+
+    pub fn step<R, W>(&mut self, writer: &mut W, reader: &mut R) -> Result<Event, RuntimeError>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        if self.done() {
+            return Ok(Event::Finished);
+        }
+
+        let pc = self.pc;
+        let event = if self.ir[pc].is_input() {
+            Event::Input(pc)
+        } else if self.ir[pc].is_output() {
+            Event::Output(pc)
+        } else {
+            Event::Executed(pc)
+        };
+
+        match self.execute(true, writer, reader) {
+            Err(RuntimeError::Io(_, _, e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                Ok(Event::WaitingForIo(pc))
+            }
+            result => result.map(|_| event),
+        }
+    }
+
+    fn execute<R, W>(
+        &mut self,
+        step: bool,
+        writer: &mut W,
+        reader: &mut R,
+    ) -> Result<(), RuntimeError>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        if let Some(profile) = &mut self.profile {
+            if self.repeat == 0 {
+                profile.count(self.pc);
+            }
+        }
+
+        if self.tracer.is_none() {
+            return self.dispatch(step, writer, reader);
+        }
+
+        let (ir, pc, head) = (self.ir, self.pc, self.head);
+
+        // the record shows the cells the instruction writes, a multiply
+        // writes every target and then clears the head
+        let offsets = match &ir[pc] {
+            Instruction::MulAdd(pairs) => pairs
+                .iter()
+                .map(|(offset, _)| *offset)
+                .chain(iter::once(0))
+                .collect(),
+            instruction => vec![instruction.offset()],
+        };
+        let before = offsets
+            .iter()
+            .map(|offset| self.peek(head, *offset))
+            .collect::<Vec<_>>();
+
+        self.dispatch(step, writer, reader)?;
+
+        for (offset, before) in offsets.iter().zip(before) {
+            let after = self.peek(head, *offset);
+
+            if let Some(tracer) = &mut self.tracer {
+                tracer
+                    .record(ir, pc, head, *offset, before, after)
+                    .map_err(|e| RuntimeError::Io(pc, head, e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // cells the tape has not grown to yet are zero
+    fn peek(&self, head: usize, offset: isize) -> u64 {
+        head.checked_add_signed(offset)
+            .and_then(|position| self.tape.cells().get(position))
+            .map_or(0, |cell| cell.to_u64())
+    }
+
+    #[inline(always)]
+    fn dispatch<R, W>(
+        &mut self,
+        step: bool,
+        writer: &mut W,
+        reader: &mut R,
+    ) -> Result<(), RuntimeError>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        let ir = self.ir;
+
+        match ir[self.pc] {
+            Instruction::NOP => (),
+
+            Instruction::Left(a) => self.head = self.left(a)?,
+
+            Instruction::Right(a) => self.head = self.right(a)?,
+
+            Instruction::Add(a) => *self.cell_mut() = self.cell().wrapping_add(C::from_usize(a)),
+
+            Instruction::Sub(a) => *self.cell_mut() = self.cell().wrapping_sub(C::from_usize(a)),
+
+            Instruction::Input(times) => {
+                if !self.input(self.head, times, step, writer, reader)? {
+                    return Ok(());
+                }
+            }
+
+            Instruction::Output(times) => self.output(self.head, times, writer)?,
+
+            Instruction::Open(close) => {
+                if self.cell().is_zero() {
+                    self.jump_to(close - 1);
+                } else if let Some(profile) = &mut self.profile {
+                    profile.iterate(self.pc);
+                }
+            }
+
+            Instruction::Close(open) => {
+                if !self.cell().is_zero() {
+                    if let Some(profile) = &mut self.profile {
+                        profile.iterate(open);
+                    }
+
+                    self.jump_to(open);
+                }
+            }
+
+            Instruction::Zero => *self.cell_mut() = C::default(),
+
+            Instruction::FindZeroLeft(a) => {
+                match C::find_zero_left(self.tape.cells(), self.head, a) {
+                    Some(position) => self.head = position,
+                    None => {
+                        // no zero down to the first cell, so the scan runs off the tape
+                        self.head %= a;
+                        self.head = self.left(a)?;
+                    }
+                }
+            }
+
+            Instruction::FindZeroRight(a) => {
+                match C::find_zero_right(self.tape.cells(), self.head, a) {
+                    Some(position) => self.head = position,
+                    None => {
+                        // cells past the end of the tape are zero once it grows
+                        self.head += (self.tape.len() - 1 - self.head) / a * a;
+                        self.head = self.right(a)?;
+                    }
+                }
+            }
+
+            Instruction::ZeroAddLeft(a) => {
+                if !self.cell().is_zero() {
+                    let target = self.left(a)?;
+                    self.tape[target] = self.tape[target].wrapping_add(self.cell());
+                    *self.cell_mut() = C::default();
+                }
+            }
+
+            Instruction::ZeroAddRight(a) => {
+                if !self.cell().is_zero() {
+                    let target = self.right(a)?;
+                    self.tape[target] = self.tape[target].wrapping_add(self.cell());
+                    *self.cell_mut() = C::default();
+                }
+            }
+
+            Instruction::MulAdd(ref pairs) => {
+                let value = self.cell();
+
+                if !value.is_zero() {
+                    for (offset, factor) in pairs {
+                        let target = self.offset(*offset)?;
+                        self.tape[target] = self.tape[target]
+                            .wrapping_add(value.wrapping_mul(C::from_isize(*factor)));
+                    }
+                    *self.cell_mut() = C::default();
+                }
+            }
+
+            Instruction::AddAt(offset, a) => {
+                let target = self.offset(offset)?;
+                self.tape[target] = self.tape[target].wrapping_add(C::from_usize(a));
+            }
+
+            Instruction::SubAt(offset, a) => {
+                let target = self.offset(offset)?;
+                self.tape[target] = self.tape[target].wrapping_sub(C::from_usize(a));
+            }
+
+            Instruction::InputAt(offset, times) => {
+                let target = self.offset(offset)?;
+
+                if !self.input(target, times, step, writer, reader)? {
+                    return Ok(());
+                }
+            }
+
+            Instruction::OutputAt(offset, times) => {
+                let target = self.offset(offset)?;
+                self.output(target, times, writer)?;
+            }
+
+            Instruction::ZeroAt(offset) => {
+                let target = self.offset(offset)?;
+                self.tape[target] = C::default();
+            }
+
+            Instruction::Set(n) => *self.cell_mut() = C::from_isize(n),
+
+            Instruction::SetAt(offset, n) => {
+                let target = self.offset(offset)?;
+                self.tape[target] = C::from_isize(n);
+            }
+        }
+
+        self.increase_pc();
+
+        Ok(())
+    }
+}
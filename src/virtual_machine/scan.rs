@@ -0,0 +1,85 @@
+use std::convert::TryInto;
+
+const BLOCK: usize = 64;
+// wider strides visit too few cells per block to pay for the mask
+const MAX_STRIDE: usize = 4;
+
+const LOW_BITS: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+const GATHER: u64 = 0x0102_0408_1020_4080;
+
+// one bit per zero cell, eight cells at a time
+fn zero_mask(block: &[u8; BLOCK]) -> u64 {
+    block.chunks_exact(8).rev().fold(0, |mask, word| {
+        let word = u64::from_le_bytes(word.try_into().unwrap());
+        // the high bit of exactly those bytes that are zero, packed into one byte
+        let zeros = !(((word & LOW_BITS) + LOW_BITS) | word) & HIGH_BITS;
+        mask << 8 | (zeros >> 7).wrapping_mul(GATHER) >> 56
+    })
+}
+
+fn block(cells: &[u8], index: usize) -> &[u8; BLOCK] {
+    cells[index..index + BLOCK].try_into().unwrap()
+}
+
+pub(crate) fn find_zero_right(cells: &[u8], start: usize, stride: usize) -> Option<usize> {
+    if stride == 1 {
+        return memchr::memchr(0, &cells[start..]).map(|index| start + index);
+    }
+
+    let mut index = start;
+
+    if stride <= MAX_STRIDE {
+        // blocks begin on a visited cell, so the same lanes are checked in each
+        let lanes = (0..BLOCK)
+            .step_by(stride)
+            .fold(0u64, |lanes, bit| lanes | 1 << bit);
+
+        while index + BLOCK <= cells.len() {
+            let zeros = zero_mask(block(cells, index)) & lanes;
+
+            if zeros != 0 {
+                return Some(index + zeros.trailing_zeros() as usize);
+            }
+
+            index += BLOCK / stride * stride;
+        }
+    }
+
+    (index..cells.len())
+        .step_by(stride)
+        .find(|&index| cells[index] == 0)
+}
+
+pub(crate) fn find_zero_left(cells: &[u8], start: usize, stride: usize) -> Option<usize> {
+    if stride == 1 {
+        return memchr::memrchr(0, &cells[..=start]);
+    }
+
+    let mut index = start;
+
+    if stride <= MAX_STRIDE {
+        let lanes = (0..BLOCK)
+            .step_by(stride)
+            .fold(0u64, |lanes, bit| lanes | 1 << (BLOCK - 1 - bit));
+
+        while index >= BLOCK - 1 {
+            let first = index + 1 - BLOCK;
+            let zeros = zero_mask(block(cells, first)) & lanes;
+
+            if zeros != 0 {
+                return Some(first + BLOCK - 1 - zeros.leading_zeros() as usize);
+            }
+
+            index = index.checked_sub(BLOCK / stride * stride)?;
+        }
+    }
+
+    loop {
+        if cells[index] == 0 {
+            return Some(index);
+        }
+
+        index = index.checked_sub(stride)?;
+    }
+}
@@ -0,0 +1,127 @@
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use thiserror::Error;
+
+use super::Cell;
+
+const MAGIC: &[u8; 4] = b"BFVM";
+const VERSION: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("i/o error: {}", .0)]
+    Io(#[from] io::Error),
+    #[error("not a brainfuck vm snapshot")]
+    BadMagic,
+    #[error("unsupported snapshot version {}", .0)]
+    UnsupportedVersion(u8),
+    #[error("snapshot has {}-bit cells, expected {}-bit cells", .0, .1)]
+    CellWidth(u32, u32),
+    #[error("snapshot was taken from a different program")]
+    Fingerprint,
+    #[error("snapshot tape of {} cells does not fit the tape", .0)]
+    TapeTooLarge(usize),
+    #[error("snapshot pc {} is past the end of the program", .0)]
+    PcPastEnd(usize),
+    #[error("snapshot head {} is past the end of the tape", .0)]
+    HeadPastEnd(usize),
+    #[error("snapshot value {} does not fit in memory", .0)]
+    Overflow(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot<C: Cell = u8> {
+    pub fingerprint: u64,
+    pub pc: usize,
+    pub head: usize,
+    pub repeat: usize,
+    pub cells: Vec<C>,
+}
+
+impl<C: Cell> Snapshot<C> {
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> Result<(), SnapshotError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, C::BITS as u8])?;
+        writer.write_all(&self.fingerprint.to_le_bytes())?;
+
+        for value in &[self.pc, self.head, self.repeat, self.cells.len()] {
+            writer.write_all(&(*value as u64).to_le_bytes())?;
+        }
+
+        let width = C::BITS as usize / 8;
+        let mut bytes = Vec::with_capacity(self.cells.len() * width);
+
+        for cell in &self.cells {
+            bytes.extend_from_slice(&cell.to_u64().to_le_bytes()[..width]);
+        }
+
+        writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+
+    pub fn read_from<R: io::Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let mut header = [0; 2];
+        reader.read_exact(&mut header)?;
+
+        if header[0] != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header[0]));
+        }
+
+        if header[1] as u32 != C::BITS {
+            return Err(SnapshotError::CellWidth(header[1] as u32, C::BITS));
+        }
+
+        let fingerprint = read_u64(reader)?;
+        let pc = read_usize(reader)?;
+        let head = read_usize(reader)?;
+        let repeat = read_usize(reader)?;
+        let length = read_usize(reader)?;
+
+        let width = C::BITS as usize / 8;
+        let size = length
+            .checked_mul(width)
+            .ok_or(SnapshotError::Overflow(length as u64))?;
+        let mut bytes = Vec::new();
+        reader.by_ref().take(size as u64).read_to_end(&mut bytes)?;
+
+        if bytes.len() != size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let cells = bytes
+            .chunks(width)
+            .map(|chunk| {
+                let mut value = [0; 8];
+                value[..width].copy_from_slice(chunk);
+                C::from_u64(u64::from_le_bytes(value))
+            })
+            .collect();
+
+        Ok(Self {
+            fingerprint,
+            pc,
+            head,
+            repeat,
+            cells,
+        })
+    }
+}
+
+fn read_u64<R: io::Read>(reader: &mut R) -> Result<u64, SnapshotError> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+fn read_usize<R: io::Read>(reader: &mut R) -> Result<usize, SnapshotError> {
+    let value = read_u64(reader)?;
+    usize::try_from(value).map_err(|_| SnapshotError::Overflow(value))
+}
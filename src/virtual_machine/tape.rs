@@ -0,0 +1,130 @@
+use std::ops;
+
+use super::Cell;
+
+const INITIAL_LENGTH: usize = 30_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TapeSize {
+    #[default]
+    Growable,
+    Max(usize),
+    Fixed(usize),
+}
+
+impl TapeSize {
+    pub fn limit(&self) -> usize {
+        match self {
+            Self::Growable => usize::MAX,
+            Self::Max(limit) | Self::Fixed(limit) => *limit,
+        }
+    }
+
+    pub fn initial_length(&self) -> usize {
+        match self {
+            Self::Fixed(length) => *length,
+            Self::Growable | Self::Max(_) => INITIAL_LENGTH.min(self.limit()),
+        }
+    }
+}
+
+pub struct Tape<C: Cell = u8> {
+    cells: Vec<C>,
+    size: TapeSize,
+}
+
+impl<C: Cell> Default for Tape<C> {
+    fn default() -> Self {
+        Self::new(TapeSize::default())
+    }
+}
+
+impl<C: Cell> ops::Index<usize> for Tape<C> {
+    type Output = C;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.cells[index]
+    }
+}
+
+impl<C: Cell> ops::IndexMut<usize> for Tape<C> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.cells[index]
+    }
+}
+
+impl<C: Cell> Tape<C> {
+    pub fn new(size: TapeSize) -> Self {
+        assert!(size.limit() > 0, "the tape needs at least one cell");
+
+        Self {
+            cells: vec![C::default(); size.initial_length()],
+            size,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn size(&self) -> TapeSize {
+        self.size
+    }
+
+    pub fn cells(&self) -> &[C] {
+        &self.cells
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [C] {
+        &mut self.cells
+    }
+
+    pub fn get(&self, index: usize) -> C {
+        self.cells.get(index).copied().unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn reserve(&mut self, index: usize) -> bool {
+        if index < self.cells.len() {
+            true
+        } else if index < self.size.limit() {
+            self.grow(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    // snapshots leave out the zero cells at the end, the tape still has to
+    // reach the head
+    pub(crate) fn replace(&mut self, mut cells: Vec<C>, head: usize) -> bool {
+        if cells.len() > self.size.limit() || head >= self.size.limit() {
+            return false;
+        }
+
+        let length = match self.size {
+            TapeSize::Fixed(length) => length,
+            TapeSize::Growable | TapeSize::Max(_) => self.size.initial_length().max(head + 1),
+        };
+
+        if cells.len() < length {
+            cells.resize(length, C::default());
+        }
+
+        self.cells = cells;
+        true
+    }
+
+    #[cold]
+    fn grow(&mut self, index: usize) {
+        let length = (index + 1)
+            .max(self.cells.len().saturating_mul(2))
+            .min(self.size.limit());
+
+        self.cells.resize(length, C::default());
+    }
+}
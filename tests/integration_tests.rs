@@ -1,6 +1,18 @@
-use brainfuck::parser::Parser;
-use brainfuck::virtual_machine::VM;
-use std::io::empty;
+use brainfuck::backend::{emit_c, emit_elf, emit_llvm, emit_rust, emit_wasm, emit_wat, Target};
+use brainfuck::ir::{BytecodeError, Instruction, Span, TextError, VerifyError, IR};
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+use brainfuck::jit::JIT;
+use brainfuck::optimizer::{OptLevel, Pass, Pipeline, RunLength};
+use brainfuck::parser::{Parser, Warning};
+use brainfuck::tracer::{TraceFormat, Tracer};
+use brainfuck::virtual_machine::{
+    Cell, EofBehavior, Event, RuntimeError, Snapshot, SnapshotError, Status, StopAt, TapeSize, VM,
+};
+use brainfuck_macro::brainfuck;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{empty, ErrorKind, Write};
+use std::process::{Command, Stdio};
 
 #[test]
 fn hello() {
@@ -58,7 +70,7 @@ Pointer :   ^
 
     parser.parse(program).unwrap();
 
-    VM::new(parser.ir()).run(&mut output, &mut empty());
+    VM::new(parser.ir()).run(&mut output, &mut empty()).unwrap();
 
     assert_eq!(output, expected.as_bytes());
 }
@@ -120,7 +132,1212 @@ http://brainfuck.org/]
 
     parser.parse(program).unwrap();
 
-    VM::new(parser.ir()).run(&mut output, &mut empty());
+    VM::new(parser.ir()).run(&mut output, &mut empty()).unwrap();
 
     assert_eq!(output, expected.as_bytes());
 }
+
+#[test]
+fn tape_grows_to_the_right() {
+    let program = format!("{}+++.", ">".repeat(100_000));
+
+    let mut output = Vec::new();
+
+    let mut parser = Parser::default();
+
+    parser.parse(&program).unwrap();
+
+    let mut vm = VM::new(parser.ir());
+
+    vm.run(&mut output, &mut empty()).unwrap();
+
+    assert_eq!(output, [3]);
+    assert!(vm.tape().len() > 100_000);
+}
+
+#[test]
+fn fixed_tape_size() {
+    let program = format!("{}+", ">".repeat(100));
+
+    let mut parser = Parser::default();
+
+    parser.parse(&program).unwrap();
+
+    let result = VM::new(parser.ir())
+        .with_tape_size(TapeSize::Fixed(100))
+        .run(&mut Vec::new(), &mut empty());
+
+    assert!(matches!(result, Err(RuntimeError::PointerOverflow(0, 0))));
+
+    let mut vm = VM::new(parser.ir()).with_tape_size(TapeSize::Max(10));
+
+    assert!(vm.head_to(9).is_ok());
+    assert!(matches!(
+        vm.head_to(10),
+        Err(RuntimeError::PointerOverflow(0, 9))
+    ));
+    assert_eq!(vm.head(), 9);
+}
+
+#[test]
+#[should_panic(expected = "at least one cell")]
+fn empty_tape() {
+    let ir = IR::default();
+    let _ = VM::new(&ir).with_tape_size(TapeSize::Max(0));
+}
+
+#[test]
+fn runtime_errors() {
+    let mut parser = Parser::default();
+
+    parser.parse("+>+<<").unwrap();
+
+    let result = VM::new(parser.ir()).run(&mut Vec::new(), &mut empty());
+
+    assert!(matches!(result, Err(RuntimeError::PointerUnderflow(2, 0))));
+
+    let mut parser = Parser::default();
+
+    parser.parse(">,").unwrap();
+
+    let result = VM::new(parser.ir()).run(&mut Vec::new(), &mut empty());
+
+    // the input is an offset instruction, the error points at the cell it reads
+    assert!(matches!(result, Err(RuntimeError::UnexpectedEof(0, 1))));
+}
+
+#[test]
+fn cell_width() {
+    let program = format!(">{}<{}[>+<[-]]>.", "+".repeat(48), "+".repeat(256));
+
+    let mut parser = Parser::default();
+
+    parser.parse(&program).unwrap();
+
+    let mut output = Vec::new();
+
+    VM::new(parser.ir()).run(&mut output, &mut empty()).unwrap();
+    VM::<u16>::new_generic(parser.ir())
+        .run(&mut output, &mut empty())
+        .unwrap();
+    VM::<u64>::new_generic(parser.ir())
+        .run(&mut output, &mut empty())
+        .unwrap();
+
+    assert_eq!(output, b"011");
+}
+
+#[test]
+fn eof_behavior() {
+    let mut parser = Parser::default();
+
+    parser.parse("+,.").unwrap();
+
+    let mut output = Vec::new();
+
+    for eof in [
+        EofBehavior::Unchanged,
+        EofBehavior::Zero,
+        EofBehavior::MinusOne,
+    ] {
+        VM::new(parser.ir())
+            .with_eof_behavior(eof)
+            .run(&mut output, &mut empty())
+            .unwrap();
+    }
+
+    VM::new(parser.ir())
+        .with_eof_behavior(EofBehavior::Zero)
+        .run(&mut output, &mut &b"a"[..])
+        .unwrap();
+
+    assert_eq!(output, [1, 0, 255, b'a']);
+}
+
+#[test]
+fn fuel() {
+    let mut parser = Parser::default();
+
+    parser.parse("+++[>+[>+<-]<-]>>.").unwrap();
+
+    let mut output = Vec::new();
+
+    let mut vm = VM::new(parser.ir());
+
+    let status = vm.run_with_fuel(5, &mut output, &mut empty()).unwrap();
+
+    assert_eq!(status, Status::OutOfFuel);
+    assert!(output.is_empty());
+
+    let status = vm.run_with_fuel(100, &mut output, &mut empty()).unwrap();
+
+    assert_eq!(status, Status::Finished);
+    assert_eq!(output, [3]);
+}
+
+#[test]
+fn step_and_run_until() {
+    let mut parser = Parser::default();
+
+    parser.parse(",[+.,]").unwrap();
+
+    let mut output = Vec::new();
+
+    let mut vm = VM::new(parser.ir()).with_eof_behavior(EofBehavior::Zero);
+
+    let mut input = b"abc".iter();
+
+    loop {
+        match vm
+            .run_until(StopAt::InputOrOutput, &mut output, &mut empty())
+            .unwrap()
+        {
+            Status::Input => {
+                let byte = input.next().map(|b| vec![*b]).unwrap_or_default();
+                let pc = vm.pc();
+                assert_eq!(
+                    vm.step(&mut output, &mut &byte[..]).unwrap(),
+                    Event::Input(pc)
+                );
+            }
+            Status::Output => assert_eq!(output.last(), Some(&(b'a' + output.len() as u8))),
+            Status::Finished => break,
+            status => panic!("unexpected status {:?}", status),
+        }
+    }
+
+    assert_eq!(output, b"bcd");
+    assert_eq!(vm.step(&mut output, &mut empty()).unwrap(), Event::Finished);
+}
+
+#[test]
+fn step_repeated_input() {
+    let mut parser = Parser::default();
+
+    parser.parse(",,.").unwrap();
+
+    let mut output = Vec::new();
+
+    let mut vm = VM::new(parser.ir());
+
+    let mut input = b"ab".iter();
+
+    while vm
+        .run_until(StopAt::Input, &mut output, &mut empty())
+        .unwrap()
+        == Status::Input
+    {
+        let byte = [*input.next().unwrap()];
+        assert_eq!(
+            vm.step(&mut output, &mut &byte[..]).unwrap(),
+            Event::Input(0)
+        );
+    }
+
+    assert_eq!(output, b"b");
+    assert_eq!(input.next(), None);
+}
+
+#[test]
+fn snapshot_restore() {
+    let mut parser = Parser::default();
+
+    parser.parse("++++[>+++<-]>[>++<-]>.").unwrap();
+
+    let mut vm = VM::<u16>::new_generic(parser.ir());
+
+    vm.run_with_fuel(3, &mut Vec::new(), &mut empty()).unwrap();
+
+    let mut bytes = Vec::new();
+
+    vm.snapshot().write_to(&mut bytes).unwrap();
+
+    let snapshot = Snapshot::read_from(&mut &bytes[..]).unwrap();
+
+    assert_eq!(snapshot, vm.snapshot());
+
+    let mut output = Vec::new();
+
+    let mut resumed = VM::<u16>::new_generic(parser.ir());
+
+    resumed.restore(&snapshot).unwrap();
+    resumed.run(&mut output, &mut empty()).unwrap();
+
+    assert_eq!(output, [24]);
+
+    assert!(matches!(
+        Snapshot::<u8>::read_from(&mut &bytes[..]),
+        Err(SnapshotError::CellWidth(16, 8))
+    ));
+
+    // the number of cells is right after the magic, version, width,
+    // fingerprint, pc, head and repeat count
+    let mut huge = bytes.clone();
+
+    huge[38..46].copy_from_slice(&u64::MAX.to_le_bytes());
+
+    assert!(matches!(
+        Snapshot::<u16>::read_from(&mut &huge[..]),
+        Err(SnapshotError::Overflow(_))
+    ));
+
+    let mut other = Parser::default();
+
+    other.parse("+").unwrap();
+
+    assert!(matches!(
+        VM::<u16>::new_generic(other.ir()).restore(&snapshot),
+        Err(SnapshotError::Fingerprint)
+    ));
+
+    let past_end = Snapshot {
+        pc: 100,
+        ..snapshot.clone()
+    };
+
+    assert!(matches!(
+        VM::<u16>::new_generic(parser.ir()).restore(&past_end),
+        Err(SnapshotError::PcPastEnd(100))
+    ));
+
+    // the head is past the last cell that is not zero
+    let mut parser = Parser::default().with_pipeline(Pipeline::new(OptLevel::O0));
+
+    parser.parse(">>>>+.").unwrap();
+
+    let mut vm = VM::new(parser.ir());
+
+    vm.run_with_fuel(4, &mut Vec::new(), &mut empty()).unwrap();
+
+    let snapshot = vm.snapshot();
+
+    assert!(snapshot.cells.is_empty());
+
+    let mut output = Vec::new();
+    let mut resumed = VM::new(parser.ir());
+
+    resumed.restore(&snapshot).unwrap();
+    resumed.run(&mut output, &mut empty()).unwrap();
+
+    assert_eq!(output, [1]);
+    assert!(matches!(
+        VM::new(parser.ir())
+            .with_tape_size(TapeSize::Fixed(4))
+            .restore(&snapshot),
+        Err(SnapshotError::HeadPastEnd(4))
+    ));
+}
+
+#[test]
+fn profiler() {
+    let program = "+++[>++.\n<-]";
+
+    let mut parser = Parser::default();
+
+    parser.parse(program).unwrap();
+
+    let mut vm = VM::new(parser.ir()).with_profiler();
+
+    vm.run(&mut Vec::new(), &mut empty()).unwrap();
+
+    let profile = vm.profile().unwrap();
+
+    assert_eq!(profile.counts(), [1, 1, 3, 3, 3, 3]);
+    assert_eq!(profile.loops(parser.ir())[0].iterations, 3);
+    assert_eq!(
+        profile.folded(parser.ir(), program),
+        "Add(3)@1:1 1\n\
+         loop@1:4;AddAt(1,2)@1:6 3\n\
+         loop@1:4;Close(1)@2:3 3\n\
+         loop@1:4;Open(5)@1:4 1\n\
+         loop@1:4;OutputAt(1,1)@1:8 3\n\
+         loop@1:4;Sub(1)@2:2 3\n"
+    );
+}
+
+#[test]
+fn trace() {
+    let mut parser = Parser::default();
+
+    parser.parse("++>+<[->+++<]>.").unwrap();
+
+    let mut trace = Vec::new();
+
+    VM::new(parser.ir())
+        .with_tracer(Tracer::new(&mut trace, TraceFormat::JsonLines))
+        .run(&mut Vec::new(), &mut empty())
+        .unwrap();
+
+    // offset instructions show the cell they change, a multiply every target
+    // and then the cleared head
+    assert_eq!(
+        String::from_utf8(trace).unwrap(),
+        "{\"pc\":0,\"instruction\":\"Add(2)\",\"beginning\":0,\"end\":1,\"head\":0,\"offset\":0,\"before\":0,\"after\":2}\n\
+         {\"pc\":1,\"instruction\":\"AddAt(1,1)\",\"beginning\":3,\"end\":3,\"head\":0,\"offset\":1,\"before\":0,\"after\":1}\n\
+         {\"pc\":2,\"instruction\":\"MulAdd(1:3)\",\"beginning\":5,\"end\":12,\"head\":0,\"offset\":1,\"before\":1,\"after\":7}\n\
+         {\"pc\":2,\"instruction\":\"MulAdd(1:3)\",\"beginning\":5,\"end\":12,\"head\":0,\"offset\":0,\"before\":2,\"after\":0}\n\
+         {\"pc\":3,\"instruction\":\"OutputAt(1,1)\",\"beginning\":14,\"end\":14,\"head\":0,\"offset\":1,\"before\":7,\"after\":7}\n\
+         {\"pc\":4,\"instruction\":\"Right(1)\",\"beginning\":13,\"end\":13,\"head\":0,\"offset\":0,\"before\":0,\"after\":0}\n"
+    );
+}
+
+#[test]
+fn multiply_loops() {
+    let mut parser = Parser::default();
+
+    parser
+        .parse("+++++[>++>>---<+<<-]>.>.>.<<<++[->>+++<<]>>.<<--[+>-<]>.")
+        .unwrap();
+
+    assert_eq!(
+        parser.to_string(),
+        "+5*(1:2,2:1,3:-3).1@1.1@2.1@3+2*(2:3).1@2-2*(1:1).1@1>1"
+    );
+
+    let mut output = Vec::new();
+
+    VM::new(parser.ir()).run(&mut output, &mut empty()).unwrap();
+
+    assert_eq!(output, [10, 5, 241, 11, 8]);
+}
+
+#[test]
+fn offset_instructions() {
+    let mut parser = Parser::default();
+
+    parser.parse(">+>+>+<<<[>>.<<-]>>>,<.").unwrap();
+
+    assert_eq!(parser.to_string(), "+1@1+1@2+1@3[.1@2-1],1@3.1@2>2");
+
+    let mut output = Vec::new();
+
+    VM::new(parser.ir())
+        .run(&mut output, &mut &b"x"[..])
+        .unwrap();
+
+    assert_eq!(output, [1]);
+}
+
+#[test]
+fn set_constants() {
+    let mut parser = Parser::default();
+
+    parser.parse("+[-]+++++.[+]--.>[-]+++[-]<.>[-]++<").unwrap();
+
+    assert_eq!(parser.to_string(), "+1=5.1=-2.1!@1.1=2@1");
+
+    let mut output = Vec::new();
+
+    VM::new(parser.ir()).run(&mut output, &mut empty()).unwrap();
+    VM::<u16>::new_generic(parser.ir())
+        .run(&mut output, &mut empty())
+        .unwrap();
+
+    assert_eq!(output, [5, 254, 254, 5, 254, 254]);
+}
+
+#[test]
+fn optimization_levels() {
+    let program = "++>[-]<[->+<] comment >.";
+    let mut outputs = Vec::new();
+
+    for (level, expected) in &[
+        (OptLevel::O0, "+1+1>1[-1]<1[-1>1+1<1]>1.1"),
+        (OptLevel::O1, "+2>1!<1m1>1.1"),
+        (OptLevel::O2, "+2!@1m1.1@1>1"),
+    ] {
+        let mut parser = Parser::default().with_pipeline(Pipeline::new(*level));
+
+        parser.parse(program).unwrap();
+
+        assert_eq!(parser.to_string(), *expected);
+
+        let mut output = Vec::new();
+
+        VM::new(parser.ir()).run(&mut output, &mut empty()).unwrap();
+        outputs.push(output);
+    }
+
+    assert_eq!(outputs, [[2], [2], [2]]);
+
+    let mut parser = Parser::default().with_pipeline(Pipeline::new(OptLevel::O0));
+
+    parser.parse(program).unwrap();
+
+    assert_eq!(parser.ir().len(), 15);
+    assert_eq!(parser.ir().span(2).beginning, 2);
+    assert_eq!(parser.ir().span(13).beginning, 22);
+}
+
+struct DropOutput;
+
+impl Pass for DropOutput {
+    fn name(&self) -> &'static str {
+        "drop-output"
+    }
+
+    fn run(&self, ir: &mut IR, _warnings: &mut Vec<Warning>) {
+        for index in 0..ir.len() {
+            if ir[index].is_output() {
+                ir[index] = Instruction::NOP;
+            }
+        }
+    }
+}
+
+#[test]
+fn custom_pass() {
+    let pipeline = Pipeline::default()
+        .with_pass(DropOutput)
+        .with_pass(RunLength);
+
+    assert_eq!(pipeline.names(), ["drop-output", "run-length"]);
+
+    let mut parser = Parser::default().with_pipeline(pipeline);
+
+    parser.parse("+.+.+.").unwrap();
+
+    assert_eq!(parser.to_string(), "+3");
+}
+
+#[test]
+fn dead_loops() {
+    let program = "[a comment, with. punctuation]+[-][>+<-]>[-].";
+
+    let mut parser = Parser::default();
+
+    parser.parse(program).unwrap();
+
+    assert_eq!(parser.to_string(), "+1!!@1.1@1>1");
+
+    let dead = parser
+        .warnings()
+        .iter()
+        .filter(|warning| matches!(warning, Warning::DeadLoop(..)))
+        .map(|warning| (warning.beginning(), warning.end()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(dead, [(0, 29), (34, 39)]);
+
+    let mut output = Vec::new();
+
+    VM::new(parser.ir()).run(&mut output, &mut empty()).unwrap();
+
+    assert_eq!(output, [0]);
+}
+
+#[test]
+fn scan_loops() {
+    // a sparse pseudo random tape, so every stride meets zeros in every lane
+    let mut state = 12345u32;
+    let cells = (0..1000)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            if (state >> 16) % 23 == 0 {
+                0
+            } else {
+                (state >> 8) as u8 | 1
+            }
+        })
+        .collect::<Vec<u8>>();
+    let wide = cells.iter().map(|c| *c as u16).collect::<Vec<_>>();
+
+    for stride in 1..=20 {
+        for start in 0..cells.len() {
+            let right = (start..cells.len())
+                .step_by(stride)
+                .find(|&index| cells[index] == 0);
+            let left = (0..=start)
+                .rev()
+                .step_by(stride)
+                .find(|&index| cells[index] == 0);
+
+            assert_eq!(u8::find_zero_right(&cells, start, stride), right);
+            assert_eq!(u8::find_zero_left(&cells, start, stride), left);
+            assert_eq!(u16::find_zero_right(&wide, start, stride), right);
+            assert_eq!(u16::find_zero_left(&wide, start, stride), left);
+        }
+    }
+
+    for (program, head) in &[("+[>]", 30_000), ("+[>>>]", 30_000), ("+[>>>>>>>]", 30_002)] {
+        let mut parser = Parser::default();
+
+        parser.parse(program).unwrap();
+
+        let mut vm = VM::new(parser.ir());
+
+        vm.restore(&Snapshot {
+            fingerprint: parser.ir().fingerprint(),
+            pc: 0,
+            head: 0,
+            repeat: 0,
+            cells: vec![1; 30_000],
+        })
+        .unwrap();
+        vm.run(&mut Vec::new(), &mut empty()).unwrap();
+
+        assert_eq!(vm.head(), *head);
+    }
+
+    let mut parser = Parser::default();
+
+    parser.parse("+>>>+>>>+[<<<]").unwrap();
+
+    let result = VM::new(parser.ir()).run(&mut Vec::new(), &mut empty());
+
+    assert!(matches!(result, Err(RuntimeError::PointerUnderflow(4, 0))));
+
+    let mut parser = Parser::default();
+
+    parser.parse("+>+>+>+>+<<<<[>>]").unwrap();
+
+    let result = VM::new(parser.ir())
+        .with_tape_size(TapeSize::Fixed(5))
+        .run(&mut Vec::new(), &mut empty());
+
+    assert!(matches!(result, Err(RuntimeError::PointerOverflow(5, 4))));
+}
+
+#[test]
+fn source_map() {
+    let program = "++ [->+<]\n>>[-]+++ [>] <.";
+
+    for level in &[OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        let mut parser = Parser::default().with_pipeline(Pipeline::new(*level));
+
+        parser.parse(program).unwrap();
+
+        let ir = parser.ir();
+        let sources = (0..ir.len())
+            .map(|pc| &program[ir.span(pc).beginning..=ir.span(pc).end])
+            .collect::<Vec<_>>();
+
+        match level {
+            OptLevel::O0 => {
+                assert_eq!(sources.len(), 21);
+                assert!(sources.iter().all(|source| source.len() == 1));
+            }
+            OptLevel::O1 => assert_eq!(
+                sources,
+                ["++", "[->+<]", ">>", "[-]", "+++", "[>]", "<", "."]
+            ),
+            OptLevel::O2 => assert_eq!(sources, ["++", "[->+<]", "[-]+++", ">>", "[>]", ".", "<"]),
+        }
+    }
+}
+
+#[test]
+fn ir_text() {
+    let text = "~<2>3+4-5,6.7[!=-8\\9/10w11m12]*(-1:2,3:-4)+1@-2-3@4,5@6.7@-8!@9=10@-11";
+
+    let ir = text.parse::<IR>().unwrap();
+
+    assert_eq!(ir.to_string(), text);
+    assert_eq!(
+        format!("{:?}", ir),
+        "NOP\nLeft(2)\nRight(3)\nAdd(4)\nSub(5)\nInput(6)\nOutput(7)\nOpen(14)\nZero\n\
+         Set(-8)\nFindZeroLeft(9)\nFindZeroRight(10)\nZeroAddLeft(11)\nZeroAddRight(12)\n\
+         Close(7)\nMulAdd(-1:2,3:-4)\nAddAt(-2,1)\nSubAt(4,3)\nInputAt(6,5)\n\
+         OutputAt(-8,7)\nZeroAt(9)\nSetAt(-11,10)"
+    );
+    assert_eq!(
+        &text[ir.span(15).beginning..=ir.span(15).end],
+        "*(-1:2,3:-4)"
+    );
+
+    let program = fs::read_to_string("programs/mandelbrot.b").unwrap();
+
+    for level in &[OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        let mut parser = Parser::default().with_pipeline(Pipeline::new(*level));
+
+        parser.parse(&program).unwrap();
+
+        let ir = parser.ir().to_string().parse::<IR>().unwrap();
+
+        assert_eq!(ir.fingerprint(), parser.ir().fingerprint());
+    }
+
+    let ir = "+5[->3+3<3] # run-length encoded\n>3."
+        .parse::<IR>()
+        .unwrap();
+
+    assert_eq!(ir.to_string(), "+5[-1>3+3<3]>3.1");
+
+    let mut output = Vec::new();
+
+    VM::new(&ir).run(&mut output, &mut empty()).unwrap();
+
+    assert_eq!(output, [15]);
+
+    assert!(matches!(
+        "+1@".parse::<IR>(),
+        Err(TextError::ExpectedNumber(3))
+    ));
+    assert!(matches!(
+        "+[>]]".parse::<IR>(),
+        Err(TextError::MismatchedBracket(4, ']'))
+    ));
+    assert!(matches!(
+        "*(1:2".parse::<IR>(),
+        Err(TextError::UnexpectedEnd(5))
+    ));
+    assert!(matches!(
+        "+99999999999999999999999".parse::<IR>(),
+        Err(TextError::NumberOutOfRange(1))
+    ));
+    assert!(matches!(
+        "+x".parse::<IR>(),
+        Err(TextError::UnexpectedCharacter(1, 'x'))
+    ));
+
+    for text in &["/0", "+\\0", "+w0", "m0", "*()", "*(0:1)", "+0@1"] {
+        assert!(matches!(text.parse::<IR>(), Err(TextError::Invalid(..))));
+    }
+
+    assert_eq!(
+        "+1 >0".parse::<IR>().unwrap_err().to_string(),
+        "degenerate instruction Right(0) at 1"
+    );
+}
+
+#[test]
+fn bytecode() {
+    let text = "~<2>3+4-5,6.7[!=-8\\9/10w11m12]*(-1:2,3:-4)+1@-2-3@4,5@6.7@-8!@9=10@-11";
+    let ir = text.parse::<IR>().unwrap();
+    let mut bytes = Vec::new();
+
+    ir.write_to(&mut bytes).unwrap();
+
+    let loaded = IR::read_from(&mut &bytes[..]).unwrap();
+
+    assert_eq!(loaded.to_string(), text);
+    assert_eq!(loaded.fingerprint(), ir.fingerprint());
+
+    let program = fs::read_to_string("programs/factor.bf").unwrap();
+    let mut parser = Parser::default();
+
+    parser.parse(&program).unwrap();
+
+    let mut bytes = Vec::new();
+
+    parser.ir().write_to(&mut bytes).unwrap();
+
+    let loaded = IR::read_from(&mut &bytes[..]).unwrap();
+    let mut output = Vec::new();
+
+    VM::new(&loaded)
+        .run(&mut output, &mut &b"1234567\n"[..])
+        .unwrap();
+
+    assert_eq!(loaded.fingerprint(), parser.ir().fingerprint());
+    assert_eq!(output, b"1234567: 127 9721\n");
+
+    let mut corrupted = bytes.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+
+    assert!(matches!(
+        IR::read_from(&mut &corrupted[..]),
+        Err(BytecodeError::Checksum)
+    ));
+
+    let mut version = bytes.clone();
+    version[4] = 99;
+
+    assert!(matches!(
+        IR::read_from(&mut &version[..]),
+        Err(BytecodeError::UnsupportedVersion(99))
+    ));
+    assert!(matches!(
+        IR::read_from(&mut &bytes[..bytes.len() - 1]),
+        Err(BytecodeError::Truncated)
+    ));
+    assert!(matches!(
+        IR::read_from(&mut &b"BFVM"[..]),
+        Err(BytecodeError::BadMagic)
+    ));
+
+    // the checksum matches, but the scan can not move by zero cells
+    let mut degenerate = IR::default();
+    let mut bytes = Vec::new();
+
+    degenerate.push(Instruction::Add(1), Span::default());
+    degenerate.push(Instruction::FindZeroRight(0), Span::default());
+    degenerate.write_to(&mut bytes).unwrap();
+
+    assert!(matches!(
+        IR::read_from(&mut &bytes[..]),
+        Err(BytecodeError::Invalid(VerifyError::Degenerate(1, _)))
+    ));
+}
+
+#[test]
+fn verify() {
+    let program = fs::read_to_string("programs/hanoi.b").unwrap();
+
+    for level in &[OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        let mut parser = Parser::default().with_pipeline(Pipeline::new(*level));
+
+        parser.parse(&program).unwrap();
+        parser.ir().verify().unwrap();
+    }
+
+    let mut ir = "+[>[-]<-]".parse::<IR>().unwrap();
+
+    ir[1] = Instruction::Open(5);
+
+    assert!(matches!(ir.verify(), Err(VerifyError::JumpTarget(1, 5, 8))));
+
+    ir.link();
+    ir[7] = Instruction::Sub(0);
+
+    assert!(matches!(ir.verify(), Err(VerifyError::Degenerate(7, _))));
+
+    for (text, error) in &[
+        ("+[", "loop opened at 1 is never closed"),
+        ("]", "loop closed at 0 was never opened"),
+    ] {
+        let mut ir = IR::default();
+
+        for instruction in text.chars() {
+            ir.push(Instruction::try_from(instruction).unwrap(), Span::default());
+        }
+
+        assert_eq!(ir.verify().unwrap_err().to_string(), *error);
+    }
+
+    assert!(matches!(
+        "*(1:2,0:1)/0".parse::<IR>(),
+        Err(TextError::Invalid(0, VerifyError::Degenerate(0, _)))
+    ));
+}
+
+struct BreakJumps;
+
+impl Pass for BreakJumps {
+    fn name(&self) -> &'static str {
+        "break-jumps"
+    }
+
+    fn run(&self, ir: &mut IR, _warnings: &mut Vec<Warning>) {
+        ir[0] = Instruction::Open(0);
+    }
+}
+
+// a pass that breaks the IR fails the parse instead of panicking
+#[test]
+fn verify_after_passes() {
+    let mut parser = Parser::default().with_pipeline(Pipeline::default().with_pass(BreakJumps));
+
+    let error = parser.parse("[-]").unwrap_err();
+
+    assert_eq!(error.beginning(), 0);
+    assert_eq!(
+        error.to_string(),
+        "optimizer left invalid IR: jump at 0 points at 0 instead of 2"
+    );
+}
+
+#[test]
+fn c_backend() {
+    let build = |source: &str, executable: &str| tool("cc", &["-O2", "-o", executable, source]);
+
+    for (case, (ir, target, input)) in backend_cases().iter().enumerate() {
+        let name = format!("c-{}-8", case);
+        let expected = run_vm::<u8>(ir, target, input);
+
+        match run_native(&name, "c", emit_c::<u8>(ir, target), input, build) {
+            Some(result) => assert_eq!(result, expected, "case {}", case),
+            None => return,
+        }
+
+        let name = format!("c-{}-16", case);
+        let expected = run_vm::<u16>(ir, target, input);
+        let result = run_native(&name, "c", emit_c::<u16>(ir, target), input, build);
+
+        assert_eq!(result.unwrap(), expected, "case {}", case);
+    }
+}
+
+#[test]
+fn llvm_backend() {
+    // without clang the module goes through the same passes one tool at a time
+    let build = |source: &str, executable: &str| {
+        if installed("clang") {
+            return tool("clang", &["-O2", "-o", executable, source]);
+        }
+
+        let bitcode = format!("{}.bc", executable);
+        let object = format!("{}.o", executable);
+
+        tool("opt", &["-O2", "-o", &bitcode, source])?;
+        tool(
+            "llc",
+            &[
+                "-filetype=obj",
+                "-relocation-model=pic",
+                "-o",
+                &object,
+                &bitcode,
+            ],
+        )?;
+        tool("cc", &["-o", executable, &object])
+    };
+
+    for (case, (ir, target, input)) in backend_cases().iter().enumerate() {
+        let name = format!("llvm-{}-8", case);
+        let expected = run_vm::<u8>(ir, target, input);
+
+        match run_native(&name, "ll", emit_llvm::<u8>(ir, target), input, build) {
+            Some(result) => assert_eq!(result, expected, "case {}", case),
+            None => return,
+        }
+
+        let name = format!("llvm-{}-64", case);
+        let expected = run_vm::<u64>(ir, target, input);
+        let result = run_native(&name, "ll", emit_llvm::<u64>(ir, target), input, build);
+
+        assert_eq!(result.unwrap(), expected, "case {}", case);
+    }
+}
+
+#[test]
+fn rust_backend() {
+    let build = |source: &str, executable: &str| tool("rustc", &["-O", "-o", executable, source]);
+
+    for (case, (ir, target, input)) in backend_cases().iter().enumerate() {
+        let name = format!("rust-{}-8", case);
+        let expected = run_vm::<u8>(ir, target, input);
+
+        match run_native(&name, "rs", emit_rust::<u8>(ir, target), input, build) {
+            Some(result) => assert_eq!(result, expected, "case {}", case),
+            None => return,
+        }
+
+        let name = format!("rust-{}-64", case);
+        let expected = run_vm::<u64>(ir, target, input);
+        let result = run_native(&name, "rs", emit_rust::<u64>(ir, target), input, build);
+
+        assert_eq!(result.unwrap(), expected, "case {}", case);
+    }
+}
+
+#[test]
+fn rust_macro() {
+    let hello = brainfuck!(
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++."
+    );
+    let mut output = Vec::new();
+
+    hello(&mut empty(), &mut output).unwrap();
+
+    assert_eq!(output, b"Hello World!\n");
+
+    let cat = brainfuck! { ,[.,] };
+    let mut output = Vec::new();
+
+    cat(&mut &b"abc"[..], &mut output).unwrap_err();
+
+    assert_eq!(output, b"abc");
+
+    let mut parser = Parser::default();
+
+    parser.parse("+>+<<").unwrap();
+
+    let expected = VM::new(parser.ir()).run(&mut Vec::new(), &mut empty());
+    let result = brainfuck!("+>+<<")(&mut empty(), &mut Vec::new());
+
+    assert!(matches!(result, Err(RuntimeError::PointerUnderflow(2, 0))));
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        expected.unwrap_err().to_string()
+    );
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+#[test]
+fn elf_backend() {
+    fn check<C: Cell>(case: usize, ir: &IR, target: &Target, input: &[u8]) {
+        let name = format!("elf-{}-{}", case, C::BITS);
+        let expected = run_vm::<C>(ir, target, input);
+
+        // without an extension the source is written to the executable itself
+        let elf = emit_elf::<C>(ir, target);
+        let build = |_: &str, executable: &str| tool("chmod", &["+x", executable]);
+        let result = run_native(&name, "", &elf, input, build);
+
+        assert_eq!(
+            result.unwrap(),
+            expected,
+            "case {} with {}-bit cells",
+            case,
+            C::BITS
+        );
+    }
+
+    for (case, (ir, target, input)) in backend_cases().iter().enumerate() {
+        check::<u8>(case, ir, target, input);
+        check::<u16>(case, ir, target, input);
+        check::<u32>(case, ir, target, input);
+        check::<u64>(case, ir, target, input);
+    }
+}
+
+// the host a browser would be, reading stdin and reporting errors like the vm
+const WASM_HOST: &str = r#"
+const fs = require('fs');
+const input = fs.readFileSync(0);
+const output = [];
+const messages = [
+  '',
+  'pointer moved left of the first cell',
+  'pointer moved past the end of the tape',
+  'unexpected end of input',
+];
+let position = 0;
+
+WebAssembly.instantiate(fs.readFileSync(process.argv[2]), {
+  env: {
+    read_byte: () => (position < input.length ? input[position++] : -1),
+    write_byte: (byte) => output.push(byte),
+  },
+}).then(({ instance }) => {
+  const { run, pc, head } = instance.exports;
+  const status = run();
+
+  process.stdout.write(Buffer.from(output));
+
+  if (status) {
+    process.stderr.write(
+      `runtime error: ${messages[status]} (pc ${pc.value}, head ${head.value})\n`,
+    );
+    process.exitCode = 70;
+  }
+});
+"#;
+
+#[test]
+fn wasm_backend() {
+    fn check<C: Cell>(case: usize, ir: &IR, target: &Target, input: &[u8]) {
+        let wasm = emit_wasm::<C>(ir, target);
+        let wat = emit_wat::<C>(ir, target);
+
+        wasmparser::Validator::new().validate_all(&wasm).unwrap();
+        assert_eq!(wat::parse_str(&wat).unwrap(), wasm);
+
+        // node stands in for a browser, behind a script run_native can start
+        let build = |source: &str, executable: &str| {
+            let module = format!("{}.wasm", executable);
+            let host = format!("{}.js", executable);
+
+            tool("node", &["-e", ""])?;
+            fs::write(&module, wat::parse_file(source).unwrap()).unwrap();
+            fs::write(&host, WASM_HOST).unwrap();
+            fs::write(
+                executable,
+                format!("#!/bin/sh\nexec node {} {}\n", host, module),
+            )
+            .unwrap();
+            tool("chmod", &["+x", executable])
+        };
+
+        let name = format!("wasm-{}-{}", case, C::BITS);
+        let expected = run_vm::<C>(ir, target, input);
+
+        if let Some(result) = run_native(&name, "wat", &wat, input, build) {
+            assert_eq!(result, expected, "case {} with {}-bit cells", case, C::BITS);
+        }
+    }
+
+    for (case, (ir, target, input)) in backend_cases().iter().enumerate() {
+        check::<u8>(case, ir, target, input);
+        check::<u16>(case, ir, target, input);
+        check::<u32>(case, ir, target, input);
+        check::<u64>(case, ir, target, input);
+    }
+}
+
+// programs that cover every instruction, runtime error and tape limit
+fn backend_cases() -> Vec<(IR, Target, &'static [u8])> {
+    let factor = fs::read_to_string("programs/factor.bf").unwrap();
+    let mut parser = Parser::default();
+
+    parser.parse(&factor).unwrap();
+
+    let every = "~>8+3-1,2.1[w1]>1[m2]<1=5[\\1]>20=1>2=1>2!<4[/2]=3*(1:2,-1:3)\
+                 +1@-2-3@4,1@6.1@-3!@9=-10@-1.2@-1";
+    vec![
+        (parser.into_ir(), Target::default(), &b"1234567\n"[..]),
+        (every.parse().unwrap(), Target::default(), b"abc"),
+        (every.parse().unwrap(), Target::default(), b"a"),
+        ("<1".parse().unwrap(), Target::default(), b""),
+        (
+            ">1,2.1".parse().unwrap(),
+            Target::default().with_eof_behavior(EofBehavior::Unchanged),
+            b"",
+        ),
+        (
+            ">1,1.1".parse().unwrap(),
+            Target::default().with_eof_behavior(EofBehavior::MinusOne),
+            b"",
+        ),
+        (">1,1@2".parse().unwrap(), Target::default(), b""),
+        (
+            "+>9+>1".parse().unwrap(),
+            Target::default().with_tape_size(TapeSize::Max(10)),
+            b"",
+        ),
+        (
+            "+>1+>1+>1+<3[/1]".parse().unwrap(),
+            Target::default().with_tape_size(TapeSize::Fixed(4)),
+            b"",
+        ),
+        (
+            ">40000+2*(1:3,40000:-7)>1.1>40000.1=-300.1+70000.1-5000000000.1"
+                .parse()
+                .unwrap(),
+            Target::default(),
+            b"",
+        ),
+    ]
+}
+
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+#[test]
+fn jit() {
+    fn check<C: Cell>(case: usize, ir: &IR, target: &Target, mut input: &[u8]) {
+        let expected = run_vm::<C>(ir, target, input);
+        let mut jit = JIT::<C>::new_generic(ir)
+            .with_eof_behavior(target.eof_behavior())
+            .with_tape_size(target.tape_size());
+        let mut output = Vec::new();
+        let result = match jit.run(&mut output, &mut input) {
+            Ok(_) => (output, String::new(), 0),
+            Err(error) => (output, format!("runtime error: {}\n", error), 70),
+        };
+
+        assert_eq!(result, expected, "case {} with {}-bit cells", case, C::BITS);
+    }
+
+    for (case, (ir, target, input)) in backend_cases().iter().enumerate() {
+        check::<u8>(case, ir, target, input);
+        check::<u16>(case, ir, target, input);
+        check::<u32>(case, ir, target, input);
+        check::<u64>(case, ir, target, input);
+    }
+
+    let mut parser = Parser::default();
+
+    parser
+        .parse(&fs::read_to_string("programs/hanoi.b").unwrap())
+        .unwrap();
+
+    let mut vm = VM::new(parser.ir());
+    let mut expected = Vec::new();
+
+    vm.run(&mut expected, &mut empty()).unwrap();
+
+    let mut jit = JIT::new(parser.ir());
+    let mut output = Vec::new();
+
+    jit.run(&mut output, &mut empty()).unwrap();
+
+    assert_eq!(output, expected);
+    assert_eq!(jit.head(), vm.head());
+    assert_eq!(jit.tape().cells(), vm.tape().cells());
+}
+
+fn run_vm<C: Cell>(ir: &IR, target: &Target, mut input: &[u8]) -> (Vec<u8>, String, i32) {
+    let mut output = Vec::new();
+    let result = VM::<C>::new_generic(ir)
+        .with_eof_behavior(target.eof_behavior())
+        .with_tape_size(target.tape_size())
+        .run(&mut output, &mut input);
+
+    match result {
+        Ok(_) => (output, String::new(), 0),
+        Err(error) => (output, format!("runtime error: {}\n", error), 70),
+    }
+}
+
+const SKIP_MISSING_TOOLS: &str = "BRAINFUCK_SKIP_MISSING_TOOLS";
+
+// runs a tool that has to succeed, a missing one fails the test unless
+// SKIP_MISSING_TOOLS is set, and then it returns None to skip the check
+fn tool(program: &str, args: &[&str]) -> Option<()> {
+    match Command::new(program).args(args).status() {
+        Ok(status) => {
+            assert!(status.success(), "{} failed", program);
+            Some(())
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            assert!(
+                std::env::var_os(SKIP_MISSING_TOOLS).is_some(),
+                "{} is not installed, set {} to skip the tests that need it",
+                program,
+                SKIP_MISSING_TOOLS
+            );
+            eprintln!("skipping, {} is not installed", program);
+            None
+        }
+        Err(error) => panic!("{}", error),
+    }
+}
+
+fn installed(program: &str) -> bool {
+    match Command::new(program)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+    {
+        Ok(_) => true,
+        Err(error) if error.kind() == ErrorKind::NotFound => false,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+// builds the source in a scratch directory and runs the executable
+fn run_native<S, F>(
+    name: &str,
+    extension: &str,
+    source: S,
+    input: &[u8],
+    build: F,
+) -> Option<(Vec<u8>, String, i32)>
+where
+    S: AsRef<[u8]>,
+    F: Fn(&str, &str) -> Option<()>,
+{
+    let directory = std::env::temp_dir().join(format!("brainfuck-{}-{}", std::process::id(), name));
+    let executable = directory.join("program");
+    let source_path = executable.with_extension(extension);
+
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(&source_path, source).unwrap();
+
+    let built = build(source_path.to_str().unwrap(), executable.to_str().unwrap());
+
+    if built.is_none() {
+        fs::remove_dir_all(&directory).unwrap();
+        return None;
+    }
+
+    let mut child = Command::new(&executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(input).unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    fs::remove_dir_all(&directory).unwrap();
+
+    Some((
+        output.stdout,
+        String::from_utf8(output.stderr).unwrap(),
+        output.status.code().unwrap(),
+    ))
+}